@@ -0,0 +1,296 @@
+use alloc::alloc::Layout;
+use core::{
+    ffi::CStr,
+    ops::Deref,
+    sync::atomic::{self, AtomicUsize, Ordering},
+};
+
+use hashbrown::raw;
+
+use crate::{
+    cache, hasher,
+    leaky_alloc::{self, InternedStringHeader},
+    sync::{Mutex, MutexGuard},
+    table::{self, CacheAligned},
+    IBytes,
+};
+
+type ArcTable = CacheAligned<Mutex<raw::RawTable<IBytes>>>;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ARC_TABLE_INIT: ArcTable = CacheAligned(Mutex::new(raw::RawTable::new()));
+
+// reference counted strings live in their own tables, so that `IStr::new` can never hand out
+// a pointer to a string that may be deallocated. Unlike the global tables, strings are removed
+// from these, so lookups have to lock the shard.
+static ARC_TABLES: [ArcTable; 64] = [ARC_TABLE_INIT; 64];
+
+fn shard(hash: u64) -> MutexGuard<'static, raw::RawTable<IBytes>> {
+    ARC_TABLES[table::shard_index(hash, ARC_TABLES.len())]
+        .0
+        .lock()
+}
+
+// The reference count is stored right before the usual header, so `IBytes`'s accessors like
+// `len` and `saved_hash` work on reference counted strings as is.
+#[repr(C)]
+struct ArcHeader {
+    count: AtomicUsize,
+    header: InternedStringHeader,
+}
+
+const COUNT_OFFSET: usize = core::mem::offset_of!(ArcHeader, header);
+const MAX_REFCOUNT: usize = isize::MAX as usize;
+
+#[repr(transparent)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ArcIStr(IBytes);
+
+impl nohash_hasher::IsEnabled for ArcIStr {}
+
+fn layout(len: usize) -> Layout {
+    let size = (COUNT_OFFSET + leaky_alloc::HEADER_PLUS_NUL_TERM)
+        .checked_add(len)
+        .expect("Overflow while calculating layout");
+
+    Layout::from_size_align(size, core::mem::align_of::<ArcHeader>())
+        .expect("Overflow while calculating layout")
+        .pad_to_align()
+}
+
+#[cold]
+#[inline(never)]
+fn create(s: &[u8], hash: u64) -> IBytes {
+    let layout = layout(s.len());
+    let ptr = unsafe { alloc::alloc::alloc(layout) }.cast::<ArcHeader>();
+
+    if ptr.is_null() {
+        alloc::alloc::handle_alloc_error(layout)
+    }
+
+    unsafe {
+        core::ptr::addr_of_mut!((*ptr).count).write(AtomicUsize::new(1));
+        leaky_alloc::write_string(core::ptr::addr_of_mut!((*ptr).header), s, hash, true)
+    }
+}
+
+#[cold]
+#[inline(never)]
+fn drop_slow(ibytes: IBytes) {
+    let hash = ibytes.saved_hash();
+    let mut table = shard(hash);
+
+    // all lookups happen while holding the shard's lock, so once the count reaches zero
+    // here no one else can get a hold of this string
+    if ArcIStr::count(ibytes).fetch_sub(1, Ordering::Release) != 1 {
+        return;
+    }
+
+    atomic::fence(Ordering::Acquire);
+
+    table.remove_entry(hash, |&entry| entry == ibytes);
+    drop(table);
+
+    unsafe { alloc::alloc::dealloc(ArcIStr::arc_header(ibytes).cast(), layout(ibytes.len())) }
+}
+
+pub fn arc_len() -> usize {
+    ARC_TABLES.iter().map(|table| table.0.lock().len()).sum()
+}
+
+#[cfg(feature = "std")]
+#[cold]
+fn abort() -> ! {
+    std::process::abort()
+}
+
+// there is no way to abort without `std`, but the count can't realistically overflow anyway
+#[cfg(not(feature = "std"))]
+#[cold]
+fn abort() -> ! {
+    panic!("ArcIStr reference count overflow")
+}
+
+impl ArcIStr {
+    #[inline]
+    fn arc_header(ibytes: IBytes) -> *mut ArcHeader {
+        unsafe { ibytes.header_ptr().cast::<u8>().sub(COUNT_OFFSET).cast() }
+    }
+
+    #[inline]
+    fn count<'a>(ibytes: IBytes) -> &'a AtomicUsize {
+        unsafe { &(*Self::arc_header(ibytes)).count }
+    }
+
+    pub fn new(s: &str) -> Self {
+        let s = s.as_bytes();
+        let hash = hasher::hash(s);
+        let table = &mut *shard(hash);
+
+        if let Some(&ibytes) = table.get(hash, |ibytes| ibytes.to_bytes() == s) {
+            // entries in the table always have a non-zero count while the lock is held
+            Self::count(ibytes).fetch_add(1, Ordering::Relaxed);
+            Self(ibytes)
+        } else {
+            let ibytes = create(s, hash);
+            cache::insert(table, ibytes, hash);
+            Self(ibytes)
+        }
+    }
+
+    pub fn get(s: &str) -> Option<Self> {
+        let s = s.as_bytes();
+        let hash = hasher::hash(s);
+        let table = &mut *shard(hash);
+
+        let &ibytes = table.get(hash, |ibytes| ibytes.to_bytes() == s)?;
+        Self::count(ibytes).fetch_add(1, Ordering::Relaxed);
+        Some(Self(ibytes))
+    }
+
+    #[inline]
+    pub fn strong_count(this: &Self) -> usize {
+        Self::count(this.0).load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub fn to_str(&self) -> &str {
+        unsafe { core::str::from_utf8_unchecked(self.to_bytes()) }
+    }
+
+    #[inline]
+    pub fn to_bytes(&self) -> &[u8] {
+        self.0.to_bytes()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    #[inline]
+    pub fn saved_hash(&self) -> u64 {
+        self.0.saved_hash()
+    }
+
+    #[inline]
+    pub fn as_cstr_ptr(&self) -> *const core::ffi::c_char {
+        self.0.as_cstr_ptr()
+    }
+
+    #[inline]
+    pub fn as_cstr(&self) -> &CStr {
+        self.0.as_cstr()
+    }
+}
+
+impl Clone for ArcIStr {
+    #[inline]
+    fn clone(&self) -> Self {
+        let old = Self::count(self.0).fetch_add(1, Ordering::Relaxed);
+
+        if old > MAX_REFCOUNT {
+            abort()
+        }
+
+        Self(self.0)
+    }
+}
+
+impl Drop for ArcIStr {
+    #[inline]
+    fn drop(&mut self) {
+        let count = Self::count(self.0);
+        let mut current = count.load(Ordering::Relaxed);
+
+        // fast path: this isn't the last handle, so the string can't be removed
+        while current > 1 {
+            match count.compare_exchange_weak(
+                current,
+                current - 1,
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return,
+                Err(actual) => current = actual,
+            }
+        }
+
+        drop_slow(self.0)
+    }
+}
+
+impl From<&str> for ArcIStr {
+    #[inline]
+    fn from(value: &str) -> Self {
+        ArcIStr::new(value)
+    }
+}
+
+impl Deref for ArcIStr {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        self.to_str()
+    }
+}
+
+impl core::fmt::Debug for ArcIStr {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.to_str().fmt(f)
+    }
+}
+
+impl core::fmt::Display for ArcIStr {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.to_str().fmt(f)
+    }
+}
+
+impl core::fmt::Pointer for ArcIStr {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[test]
+fn test_simple() {
+    let a = ArcIStr::new("arc hello");
+    let b = ArcIStr::new("arc hello");
+    assert_eq!(a, b);
+    assert_eq!(ArcIStr::strong_count(&a), 2);
+    assert_eq!(a.saved_hash(), hasher::hash(b"arc hello"));
+    assert_ne!(a.as_cstr_ptr(), crate::IStr::new("arc hello").as_cstr_ptr());
+}
+
+#[test]
+fn test_reclaim() {
+    let a = ArcIStr::new("arc reclaim");
+    let b = a.clone();
+    drop(a);
+    assert_eq!(ArcIStr::get("arc reclaim").as_ref(), Some(&b));
+    drop(b);
+    assert_eq!(ArcIStr::get("arc reclaim"), None);
+}
+
+#[test]
+fn test_threads() {
+    std::thread::scope(|s| {
+        for _ in 0..8 {
+            s.spawn(|| {
+                for i in 0..1024 {
+                    let a = ArcIStr::new(&format!("arc thread {}", i % 16));
+                    let b = a.clone();
+                    assert_eq!(a, ArcIStr::new(&b));
+                }
+            });
+        }
+    });
+
+    assert_eq!(ArcIStr::get("arc thread 0"), None);
+}
@@ -0,0 +1,234 @@
+use alloc::vec::Vec;
+use core::{fmt, str::Utf8Error};
+
+use crate::{IBytes, IStr};
+
+// `istr::format!` interns a formatted string without allocating a `String` for it, the string
+// is formatted into a reused thread-local buffer instead.
+#[macro_export]
+macro_rules! format {
+    ($($arg:tt)*) => {
+        $crate::__private::format(::core::format_args!($($arg)*))
+    };
+}
+
+// Builds up a string to intern piece by piece. Interning it clears the builder, so its buffer
+// can be reused for the next string.
+#[derive(Debug, Default, Clone)]
+pub struct IStrBuilder {
+    buffer: Vec<u8>,
+    // set once bytes were written with `io::Write`, which may not be valid UTF-8
+    unchecked: bool,
+}
+
+impl IStrBuilder {
+    pub const fn new() -> Self {
+        Self {
+            buffer: Vec::new(),
+            unchecked: false,
+        }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            buffer: Vec::with_capacity(capacity),
+            unchecked: false,
+        }
+    }
+
+    #[inline]
+    pub fn push_str(&mut self, s: &str) {
+        self.buffer.extend_from_slice(s.as_bytes())
+    }
+
+    #[inline]
+    pub fn push(&mut self, c: char) {
+        self.push_str(c.encode_utf8(&mut [0; 4]))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+        self.unchecked = false;
+    }
+
+    pub fn intern_bytes(&mut self) -> IBytes {
+        let ibytes = IBytes::new(&self.buffer);
+        self.clear();
+        ibytes
+    }
+
+    // If anything was written with `io::Write` the contents are checked first, and are left in
+    // the builder if they aren't valid UTF-8
+    pub fn intern(&mut self) -> Result<IStr, Utf8Error> {
+        if self.unchecked {
+            core::str::from_utf8(&self.buffer)?;
+        }
+
+        Ok(unsafe { IStr::from_utf8_unchecked(self.intern_bytes()) })
+    }
+}
+
+impl fmt::Write for IStrBuilder {
+    #[inline]
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_str(s);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::io::Write for IStrBuilder {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        self.unchecked = true;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// the most the scratch buffer keeps between uses, so one huge string doesn't pin that much memory
+// for the rest of the thread's life
+#[cfg(feature = "std")]
+const SCRATCH_CAPACITY: usize = 4096;
+
+// A thread-local buffer for building strings before they are interned, used by `istr::format!`
+// and the normalizing interner. It's empty when `f` is called. Without `std`, or if the buffer is
+// already in use because a `Display` impl or a normalizer interned a string itself, a new buffer
+// is used instead.
+pub(crate) fn with_scratch<O>(f: impl FnOnce(&mut Vec<u8>) -> O) -> O {
+    #[cfg(feature = "std")]
+    {
+        thread_local! {
+            static SCRATCH: std::cell::RefCell<Vec<u8>> = const { std::cell::RefCell::new(Vec::new()) };
+        }
+
+        SCRATCH.with(|scratch| {
+            let mut fallback = Vec::new();
+            let mut guard = scratch.try_borrow_mut();
+            let buffer = guard.as_deref_mut().unwrap_or(&mut fallback);
+            // a previous call may have panicked halfway through
+            buffer.clear();
+
+            let output = f(buffer);
+
+            if buffer.capacity() > SCRATCH_CAPACITY {
+                buffer.clear();
+                buffer.shrink_to(SCRATCH_CAPACITY);
+            }
+
+            output
+        })
+    }
+
+    #[cfg(not(feature = "std"))]
+    f(&mut Vec::new())
+}
+
+fn with_buffer<O>(f: impl FnOnce(&mut IStrBuilder) -> O) -> O {
+    with_scratch(|buffer| {
+        let mut builder = IStrBuilder {
+            buffer: core::mem::take(buffer),
+            unchecked: false,
+        };
+        let output = f(&mut builder);
+        *buffer = builder.buffer;
+        output
+    })
+}
+
+pub fn format(args: fmt::Arguments<'_>) -> IStr {
+    if let Some(s) = args.as_str() {
+        return IStr::new(s);
+    }
+
+    with_buffer(|builder| {
+        fmt::Write::write_fmt(builder, args)
+            .expect("a formatting trait implementation returned an error");
+
+        // only written to with `fmt::Write`, so it's valid UTF-8
+        unsafe { IStr::from_utf8_unchecked(builder.intern_bytes()) }
+    })
+}
+
+pub(crate) fn concat(strings: &[IStr]) -> IStr {
+    match strings {
+        [] => IStr::empty(),
+        [istr] => *istr,
+        _ => with_buffer(|builder| {
+            builder
+                .buffer
+                .reserve(strings.iter().map(|istr| istr.len()).sum());
+            strings.iter().for_each(|istr| builder.push_str(istr));
+            unsafe { IStr::from_utf8_unchecked(builder.intern_bytes()) }
+        }),
+    }
+}
+
+#[test]
+fn test_format() {
+    let (a, b) = (IStr::new("format a"), 42);
+
+    assert_eq!(format!("{a}::{b}"), IStr::new("format a::42"));
+    assert_eq!(format!("format literal"), IStr::new("format literal"));
+
+    struct Nested;
+
+    impl fmt::Display for Nested {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(&format!("nested {}", 1))
+        }
+    }
+
+    assert_eq!(format!("{}!", Nested), IStr::new("nested 1!"));
+}
+
+#[test]
+fn test_concat() {
+    let parts = ["concat a", "::", "concat b"].map(IStr::new);
+
+    assert_eq!(IStr::concat(&parts), IStr::new("concat a::concat b"));
+    assert_eq!(IStr::concat(&parts[..1]), parts[0]);
+    assert_eq!(IStr::concat(&[]), IStr::empty());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_scratch_shrinks() {
+    let large = "x".repeat(1024 * 1024);
+    assert_eq!(format!("{large}{}", 1).len(), large.len() + 1);
+
+    with_scratch(|buffer| assert!(buffer.capacity() <= SCRATCH_CAPACITY));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_builder() {
+    use std::io::Write;
+
+    let mut builder = IStrBuilder::new();
+    fmt::Write::write_fmt(&mut builder, format_args!("builder {}", 1)).unwrap();
+    builder.push(' ');
+    builder.write_all(b"bytes").unwrap();
+
+    assert_eq!(builder.intern(), Ok(IStr::new("builder 1 bytes")));
+    assert!(builder.is_empty());
+
+    builder.write_all(b"\xff").unwrap();
+    assert!(builder.intern().is_err());
+    assert_eq!(builder.intern_bytes(), IBytes::new(b"\xff"));
+}
@@ -1,42 +1,113 @@
-use std::cell::RefCell;
-use std::sync::{Mutex, MutexGuard, PoisonError};
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 use hashbrown::raw;
 
-use crate::{hasher, leaky_alloc, IBytes};
+#[cfg(not(feature = "single-threaded"))]
+use crate::table::ShardedTable;
+#[cfg(feature = "single-threaded")]
+use crate::table::UnsyncTable;
+use crate::{hasher, leaky_alloc, IBytes, IStr};
+
+#[cfg(not(feature = "single-threaded"))]
+pub(crate) static TABLES: ShardedTable = ShardedTable::new();
+
+// with `single-threaded` the global table is only ever used from one thread, so it doesn't need
+// shards or a thread-local cache in front of it
+#[cfg(feature = "single-threaded")]
+pub(crate) static TABLES: UnsyncTable = UnsyncTable::new();
+
+#[cfg(feature = "std")]
+static LOCAL_CACHE_CAPACITY: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+// The thread-local cache in front of `TABLES`. Once it reaches `LOCAL_CACHE_CAPACITY` strings,
+// it evicts the strings that haven't been used recently using the CLOCK algorithm: every hit
+// sets an entry's referenced bit, and the hand sweeps over the buckets clearing the bits until
+// it finds an entry that wasn't referenced since the last sweep.
+//
+// With `single-threaded` the cache is never used, so it's always empty.
+#[cfg(feature = "std")]
+struct LocalCache {
+    table: raw::RawTable<LocalEntry>,
+    hand: usize,
+}
 
-#[repr(align(128))]
-struct CacheAlignedTable {
-    table: Mutex<raw::RawTable<IBytes>>,
+#[cfg(feature = "std")]
+struct LocalEntry {
+    ibytes: IBytes,
+    referenced: bool,
 }
 
-#[allow(clippy::declare_interior_mutable_const)]
-const CACHE_ALIGN_TABLE_INIT: CacheAlignedTable = CacheAlignedTable {
-    table: Mutex::new(raw::RawTable::new()),
-};
+#[cfg(feature = "std")]
+#[cfg_attr(feature = "single-threaded", allow(dead_code))]
+impl LocalCache {
+    const fn new() -> Self {
+        Self {
+            table: raw::RawTable::new(),
+            hand: 0,
+        }
+    }
+
+    #[inline]
+    fn get(&mut self, s: &[u8], hash: u64) -> Option<IBytes> {
+        let entry = self
+            .table
+            .get_mut(hash, |entry| entry.ibytes.to_bytes() == s)?;
+        entry.referenced = true;
+        Some(entry.ibytes)
+    }
+
+    fn insert(&mut self, ibytes: IBytes, hash: u64) {
+        self.insert_bounded(ibytes, hash, LOCAL_CACHE_CAPACITY.load(Ordering::Relaxed))
+    }
+
+    fn insert_bounded(&mut self, ibytes: IBytes, hash: u64, capacity: usize) {
+        if capacity == 0 {
+            // the strings cached under a larger capacity still have to go
+            self.table.clear();
+            return;
+        }
+
+        while self.table.len() >= capacity {
+            self.evict();
+        }
+
+        let entry = LocalEntry {
+            ibytes,
+            referenced: false,
+        };
+
+        self.table
+            .insert(hash, entry, |entry| entry.ibytes.saved_hash());
+    }
 
-// choose a decently large prime number to prevent cache collisions
-static TABLES: [CacheAlignedTable; 64] = [CACHE_ALIGN_TABLE_INIT; 64];
+    fn evict(&mut self) {
+        loop {
+            let index = self.hand % self.table.buckets();
+            self.hand = index + 1;
 
-// Constant for h2 function that grabing the top 7 bits of the hash.
-const MIN_HASH_LEN: usize = if core::mem::size_of::<usize>() < core::mem::size_of::<u64>() {
-    core::mem::size_of::<usize>()
-} else {
-    core::mem::size_of::<u64>()
-};
+            if !unsafe { self.table.is_bucket_full(index) } {
+                continue;
+            }
 
-fn table_for(hash: u64) -> MutexGuard<'static, raw::RawTable<IBytes>> {
-    let index = (hash >> (MIN_HASH_LEN * 4)) % TABLES.len() as u64;
+            let bucket = unsafe { self.table.bucket(index) };
+            let entry = unsafe { bucket.as_mut() };
 
-    TABLES[index as usize]
-        .table
-        .lock()
-        .unwrap_or_else(PoisonError::into_inner)
+            if entry.referenced {
+                entry.referenced = false;
+            } else {
+                unsafe { self.table.erase(bucket) };
+                return;
+            }
+        }
+    }
 }
 
-fn with_local_table<O>(f: impl FnOnce(&mut raw::RawTable<IBytes>) -> O) -> O {
+#[cfg(feature = "std")]
+fn with_local_table<O>(f: impl FnOnce(&mut LocalCache) -> O) -> O {
     thread_local! {
-        static LOCAL_TABLE: RefCell<raw::RawTable<IBytes>> = const { RefCell::new(raw::RawTable::new()) };
+        static LOCAL_TABLE: std::cell::RefCell<LocalCache> = const { std::cell::RefCell::new(LocalCache::new()) };
     }
 
     #[cold]
@@ -48,22 +119,59 @@ fn with_local_table<O>(f: impl FnOnce(&mut raw::RawTable<IBytes>) -> O) -> O {
     LOCAL_TABLE.with(|table| f(&mut *table.try_borrow_mut().unwrap_or_else(|_| reentrant())))
 }
 
+#[cfg(feature = "std")]
 pub fn clear_local_cache() {
-    with_local_table(|table| *table = raw::RawTable::new())
+    with_local_table(|table| *table = LocalCache::new())
 }
 
+#[cfg(feature = "std")]
 pub fn local_cache_size() -> usize {
-    with_local_table(|table| table.len())
+    with_local_table(|table| table.table.len())
 }
 
-pub fn tables() -> impl Iterator<Item = MutexGuard<'static, raw::RawTable<IBytes>>> {
-    TABLES
-        .iter()
-        .map(|table| table.table.lock().unwrap_or_else(PoisonError::into_inner))
+// Limits the number of strings in each thread's local cache, threads whose cache is over the new
+// capacity evict strings the next time they insert one. The default is `usize::MAX`.
+#[cfg(feature = "std")]
+pub fn set_local_cache_capacity(capacity: usize) {
+    LOCAL_CACHE_CAPACITY.store(capacity, Ordering::Relaxed)
+}
+
+#[cfg(feature = "std")]
+pub fn local_cache_capacity() -> usize {
+    LOCAL_CACHE_CAPACITY.load(Ordering::Relaxed)
+}
+
+// Lookups in the global table never lock, so strings can be interned while iterating. The
+// iterator sees every string interned before it was created, and may or may not see the ones
+// interned while it's running.
+pub fn items() -> impl Iterator<Item = IBytes> {
+    TABLES.iter()
+}
+
+// A weakly consistent copy of the global table, taken before this returns. It has every string
+// interned before this was called, strings interned by other threads while the shards are being
+// copied may or may not be included.
+pub fn items_snapshot() -> impl Iterator<Item = IBytes> {
+    let mut items = Vec::with_capacity(len());
+    items.extend(TABLES.iter());
+    items.into_iter()
+}
+
+// a snapshot sorted by the strings' bytes, so the order doesn't depend on their hashes or the
+// order they were interned in
+pub fn items_sorted() -> impl Iterator<Item = IBytes> {
+    let mut items = items_snapshot().collect::<Vec<_>>();
+    items.sort_unstable_by_key(|ibytes| ibytes.to_bytes());
+    items.into_iter()
+}
+
+// every string that is valid UTF-8
+pub fn istr_items() -> impl Iterator<Item = IStr> {
+    items().filter_map(IStr::try_from_utf8)
 }
 
 pub fn len() -> usize {
-    tables().map(|table| table.len()).sum()
+    TABLES.len()
 }
 
 #[cfg(ISTR_GLOBAL_CACHE_CLEAR)]
@@ -73,52 +181,35 @@ pub fn len() -> usize {
 // NOTE: This will not clear the thread-local cache, so you may still get existing strings
 // if that cache is used.
 pub fn clear_global_cache() {
-    tables().for_each(|mut table| table.clear())
+    TABLES.clear()
 }
 
-fn insert(table: &mut raw::RawTable<IBytes>, ibytes: IBytes, hash: u64) {
+pub(crate) fn insert(table: &mut raw::RawTable<IBytes>, ibytes: IBytes, hash: u64) {
     table.insert(hash, ibytes, |ibytes| ibytes.saved_hash());
 }
 
-#[cold]
-#[inline(never)]
-fn create(table: &mut raw::RawTable<IBytes>, s: &[u8], hash: u64) -> IBytes {
-    let ibytes = leaky_alloc::with_hash_bytes(s, hash, false);
-    insert(table, ibytes, hash);
-    ibytes
-}
-
-fn new_imp(s: &[u8], hash: u64) -> IBytes {
-    let table = &mut *table_for(hash);
-
-    let ibytes = if let Some(ibytes) = table.get(hash, |ibytes| ibytes.to_bytes() == s).copied() {
-        ibytes
-    } else {
-        create(table, s, hash)
-    };
-
-    ibytes
+pub(crate) fn new_imp(s: &[u8], hash: u64) -> IBytes {
+    TABLES.get_or_insert_with(s, hash, || leaky_alloc::with_hash_bytes(s, hash, false))
 }
 
+#[cfg(all(feature = "std", not(feature = "single-threaded")))]
 #[cold]
-fn new_imp_slow(s: &[u8], hash: u64, local_table: &mut raw::RawTable<IBytes>) -> IBytes {
+fn new_imp_slow(s: &[u8], hash: u64, local_table: &mut LocalCache) -> IBytes {
     let ibytes = new_imp(s, hash);
-    insert(local_table, ibytes, hash);
+    local_table.insert(ibytes, hash);
     ibytes
 }
 
 fn get_imp(s: &[u8], hash: u64) -> Option<IBytes> {
-    let table = &mut *table_for(hash);
-
-    table.get(hash, |ibytes| ibytes.to_bytes() == s).copied()
+    TABLES.get(s, hash)
 }
 
+#[cfg(all(feature = "std", not(feature = "single-threaded")))]
 #[cold]
 #[inline(never)]
-fn get_imp_slow(s: &[u8], hash: u64, local_table: &mut raw::RawTable<IBytes>) -> Option<IBytes> {
-    let table = &mut *table_for(hash);
-    let ibytes = table.get(hash, |ibytes| ibytes.to_bytes() == s).copied()?;
-    insert(local_table, ibytes, hash);
+fn get_imp_slow(s: &[u8], hash: u64, local_table: &mut LocalCache) -> Option<IBytes> {
+    let ibytes = get_imp(s, hash)?;
+    local_table.insert(ibytes, hash);
     Some(ibytes)
 }
 
@@ -128,12 +219,15 @@ pub fn new_skip_local(s: &[u8]) -> IBytes {
 }
 
 pub fn new(s: &[u8]) -> IBytes {
-    let hash = hasher::hash(s);
+    new_imp_local(s, hasher::hash(s))
+}
 
+// `hash` must be `hasher::hash(s)`, otherwise the returned string won't be equal to the one
+// returned by `new`
+#[cfg(all(feature = "std", not(feature = "single-threaded")))]
+pub(crate) fn new_imp_local(s: &[u8], hash: u64) -> IBytes {
     with_local_table(|local_table| {
-        let ibytes = local_table
-            .get(hash, |ibytes| ibytes.to_bytes() == s)
-            .copied();
+        let ibytes = local_table.get(s, hash);
 
         if let Some(ibytes) = ibytes {
             return ibytes;
@@ -149,12 +243,13 @@ pub fn get_skip_local(s: &[u8]) -> Option<IBytes> {
 }
 
 pub fn get(s: &[u8]) -> Option<IBytes> {
-    let hash = hasher::hash(s);
+    get_imp_local(s, hasher::hash(s))
+}
 
+#[cfg(all(feature = "std", not(feature = "single-threaded")))]
+pub(crate) fn get_imp_local(s: &[u8], hash: u64) -> Option<IBytes> {
     with_local_table(|local_table| {
-        let ibytes = local_table
-            .get(hash, |ibytes| ibytes.to_bytes() == s)
-            .copied();
+        let ibytes = local_table.get(s, hash);
 
         if let Some(ibytes) = ibytes {
             return Some(ibytes);
@@ -164,6 +259,97 @@ pub fn get(s: &[u8]) -> Option<IBytes> {
     })
 }
 
+// Looks up every string in the local cache and then the global table, borrowing the local cache
+// once for the whole batch
+#[cfg(all(feature = "std", not(feature = "single-threaded")))]
+fn get_many_imp<S: AsRef<[u8]>>(strings: &[S], hashes: &[u64]) -> Vec<Option<IBytes>> {
+    with_local_table(|local_table| {
+        strings
+            .iter()
+            .zip(hashes)
+            .map(|(s, &hash)| {
+                let s = s.as_ref();
+
+                match local_table.get(s, hash) {
+                    Some(ibytes) => Some(ibytes),
+                    None => get_imp_slow(s, hash, local_table),
+                }
+            })
+            .collect()
+    })
+}
+
+// without `std`, or with `single-threaded`, there is no thread-local cache, every lookup goes
+// straight to `TABLES`
+#[cfg(any(not(feature = "std"), feature = "single-threaded"))]
+pub(crate) fn new_imp_local(s: &[u8], hash: u64) -> IBytes {
+    new_imp(s, hash)
+}
+
+#[cfg(any(not(feature = "std"), feature = "single-threaded"))]
+pub(crate) fn get_imp_local(s: &[u8], hash: u64) -> Option<IBytes> {
+    get_imp(s, hash)
+}
+
+#[cfg(any(not(feature = "std"), feature = "single-threaded"))]
+fn get_many_imp<S: AsRef<[u8]>>(strings: &[S], hashes: &[u64]) -> Vec<Option<IBytes>> {
+    strings
+        .iter()
+        .zip(hashes)
+        .map(|(s, &hash)| get_imp(s.as_ref(), hash))
+        .collect()
+}
+
+fn hash_many<S: AsRef<[u8]>>(strings: &[S]) -> Vec<u64> {
+    strings.iter().map(|s| hasher::hash(s.as_ref())).collect()
+}
+
+// Only the strings that aren't interned yet are inserted, so the shards are grown once for the
+// distinct misses instead of for the whole batch. Batches that are mostly hits don't grow the
+// table at all.
+pub fn new_many<S: AsRef<[u8]>>(strings: &[S]) -> Vec<IBytes> {
+    let hashes = hash_many(strings);
+    let found = get_many_imp(strings, &hashes);
+
+    // the index of the first occurrence of each distinct miss
+    let mut misses = raw::RawTable::<usize>::new();
+
+    for (index, _) in found
+        .iter()
+        .enumerate()
+        .filter(|(_, found)| found.is_none())
+    {
+        let s = strings[index].as_ref();
+        let hash = hashes[index];
+
+        if misses
+            .get(hash, |&miss| strings[miss].as_ref() == s)
+            .is_none()
+        {
+            misses.insert(hash, index, |&miss| hashes[miss]);
+        }
+    }
+
+    if !misses.is_empty() {
+        let miss_hashes = misses
+            .into_iter()
+            .map(|miss| hashes[miss])
+            .collect::<Vec<_>>();
+        TABLES.reserve_hashes(&miss_hashes);
+    }
+
+    found
+        .into_iter()
+        .zip(strings)
+        .zip(hashes)
+        .map(|((found, s), hash)| found.unwrap_or_else(|| new_imp_local(s.as_ref(), hash)))
+        .collect()
+}
+
+pub fn get_many<S: AsRef<[u8]>>(strings: &[S]) -> Vec<Option<IBytes>> {
+    get_many_imp(strings, &hash_many(strings))
+}
+
 #[test]
 fn test_simple() {
     assert_eq!(new(b"hello"), new(b"hello"))
@@ -181,3 +367,117 @@ fn test_large_string() {
     let large = include_bytes!("../../fixtures/large_string.txt");
     assert_eq!(new(large), new(large))
 }
+
+#[test]
+fn test_many_batch() {
+    let strings = (0..4096).map(|i| format!("batch {i}")).collect::<Vec<_>>();
+
+    assert!(get_many(&strings[..2]).iter().all(Option::is_none));
+
+    let batch = new_many(&strings);
+
+    for (s, ibytes) in strings.iter().zip(&batch) {
+        assert_eq!(new(s.as_bytes()), *ibytes);
+    }
+
+    assert_eq!(
+        get_many(&strings).into_iter().collect::<Option<Vec<_>>>(),
+        Some(batch)
+    );
+}
+
+#[test]
+fn test_many_batch_hits() {
+    let strings = (0..4096)
+        .map(|i| format!("batch hit {}", i % 64))
+        .collect::<Vec<_>>();
+
+    for s in &strings[..64] {
+        new_skip_local(s.as_bytes());
+    }
+
+    // every string is already interned, so the batch must not grow any shard
+    let grown = crate::table::grown();
+    let batch = new_many(&strings);
+    assert_eq!(crate::table::grown(), grown);
+
+    for (s, ibytes) in strings.iter().zip(batch) {
+        assert_eq!(ibytes.to_bytes(), s.as_bytes());
+    }
+}
+
+#[test]
+fn test_with_hash() {
+    let hash = hasher::hash(b"pre hashed");
+    assert_eq!(IBytes::get_with_hash(b"pre hashed", hash), None);
+
+    let ibytes = unsafe { IBytes::new_with_hash(b"pre hashed", hash) };
+    assert_eq!(ibytes.saved_hash(), hash);
+    assert_eq!(ibytes, new(b"pre hashed"));
+    assert_eq!(IBytes::get_with_hash(b"pre hashed", hash), Some(ibytes));
+
+    // a wrong hash only makes the lookup miss
+    assert_eq!(IBytes::get_with_hash(b"pre hashed", !hash), None);
+}
+
+#[test]
+fn test_items() {
+    let valid = new(b"items valid");
+    let invalid = new(b"items \xff invalid");
+
+    for (i, _) in items().enumerate().take(64) {
+        // interning while iterating must not block
+        new(format!("items {i}").as_bytes());
+    }
+
+    let snapshot = items_snapshot().collect::<Vec<_>>();
+    assert!(snapshot.contains(&valid) && snapshot.contains(&invalid));
+    assert!(!snapshot.contains(&new(b"items after snapshot")));
+
+    let sorted = items_sorted().collect::<Vec<_>>();
+    assert!(sorted
+        .windows(2)
+        .all(|w| w[0].to_bytes() <= w[1].to_bytes()));
+
+    let istrs = istr_items().collect::<Vec<_>>();
+    assert!(istrs.iter().any(|istr| istr.to_ibytes() == valid));
+    assert!(istrs.iter().all(|istr| istr.to_ibytes() != invalid));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_bounded_local_cache() {
+    let mut cache = LocalCache::new();
+    let hot = new_skip_local(b"clock hot");
+
+    cache.insert_bounded(hot, hot.saved_hash(), 16);
+
+    for i in 0..256 {
+        let ibytes = new_skip_local(format!("clock {i}").as_bytes());
+        assert_eq!(cache.get(b"clock hot", hot.saved_hash()), Some(hot));
+        cache.insert_bounded(ibytes, ibytes.saved_hash(), 16);
+        assert!(cache.table.len() <= 16);
+    }
+
+    assert_eq!(cache.get(b"clock hot", hot.saved_hash()), Some(hot));
+}
+
+#[cfg(all(feature = "std", not(feature = "single-threaded")))]
+#[test]
+fn test_shrink_local_cache() {
+    // the capacity is shared with the other tests, which only makes their caches smaller
+    for i in 0..64 {
+        new(format!("shrink {i}").as_bytes());
+    }
+    assert!(local_cache_size() >= 64);
+
+    set_local_cache_capacity(8);
+    new(b"shrink to 8");
+    assert_eq!(local_cache_size(), 8);
+
+    set_local_cache_capacity(0);
+    new(b"shrink to 0");
+    assert_eq!(local_cache_size(), 0);
+
+    set_local_cache_capacity(usize::MAX);
+}
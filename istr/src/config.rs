@@ -0,0 +1,279 @@
+use alloc::alloc::Layout;
+#[cfg(feature = "std")]
+use alloc::string::String;
+
+use crate::sync::OnceLock;
+
+/// Controls how the arenas backing interned strings request memory. The config is fixed the first
+/// time a string is allocated, either by `install` or from the environment (only with `std`):
+///
+/// `ISTR_ARENA_INITIAL_SIZE`, `ISTR_ARENA_GROWTH_FACTOR`, `ISTR_ARENA_MAX_CHUNK_SIZE` and
+/// `ISTR_ARENA_LARGE_STRING_CUTOFF`, all sizes are in bytes. Values that can't be parsed or are
+/// out of range are ignored.
+#[derive(Clone, Copy)]
+pub struct ArenaConfig {
+    initial_size: usize,
+    growth_factor: usize,
+    max_chunk_size: usize,
+    large_string_cutoff: Option<usize>,
+    allocator: &'static dyn ChunkAllocator,
+}
+
+/// Provides the memory for arena chunks and the dedicated allocations for large strings, for
+/// example to back them with huge pages or keep them in a separate heap.
+///
+/// Memory allocated by the global interner is never deallocated, memory allocated for an
+/// `Interner` is deallocated when it's dropped.
+///
+/// # Safety
+///
+/// `alloc` must either return null, or a pointer to a block of memory that fits `layout` and
+/// stays valid until it is passed to `dealloc`
+pub unsafe trait ChunkAllocator: Sync {
+    /// Returns null if the memory couldn't be allocated
+    fn alloc(&self, layout: Layout) -> *mut u8;
+
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `alloc` on this allocator with the same `layout`
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout);
+}
+
+/// The default allocator, which uses the global allocator
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdAllocator;
+
+unsafe impl ChunkAllocator for StdAllocator {
+    fn alloc(&self, layout: Layout) -> *mut u8 {
+        unsafe { alloc::alloc::alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        alloc::alloc::dealloc(ptr, layout)
+    }
+}
+
+static CONFIG: OnceLock<ArenaConfig> = OnceLock::new();
+
+// small enough to hold the chunk's header and a few strings
+const MIN_CHUNK_SIZE: usize = 256;
+
+// leaves plenty of room below `isize::MAX` for the chunk's header and padding
+const MAX_CHUNK_SIZE: usize = isize::MAX as usize / 2;
+
+pub(crate) fn get() -> &'static ArenaConfig {
+    #[cfg(feature = "std")]
+    return CONFIG.get_or_init(ArenaConfig::from_env);
+
+    #[cfg(not(feature = "std"))]
+    return CONFIG.get_or_init(ArenaConfig::new);
+}
+
+impl Default for ArenaConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ArenaConfig {
+    /// The default config: 1 MiB initial chunks that double in size, using the global allocator
+    pub const fn new() -> Self {
+        Self {
+            initial_size: 1024 * 1024,
+            growth_factor: 2,
+            max_chunk_size: MAX_CHUNK_SIZE,
+            large_string_cutoff: None,
+            allocator: &StdAllocator,
+        }
+    }
+
+    /// The default config, overridden by any environment variables that are set
+    #[cfg(feature = "std")]
+    pub fn from_env() -> Self {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    #[cfg(feature = "std")]
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Self {
+        let parse = |name| var(name)?.trim().parse::<usize>().ok();
+        let parse_size = |name| parse(name).filter(|&size| size <= MAX_CHUNK_SIZE);
+        let mut config = Self::new();
+
+        if let Some(size) =
+            parse_size("ISTR_ARENA_INITIAL_SIZE").filter(|&size| size >= MIN_CHUNK_SIZE)
+        {
+            config.initial_size = size;
+            config.max_chunk_size = config.max_chunk_size.max(size);
+        }
+
+        if let Some(factor) = parse("ISTR_ARENA_GROWTH_FACTOR").filter(|&factor| factor >= 1) {
+            config.growth_factor = factor;
+        }
+
+        if let Some(size) =
+            parse_size("ISTR_ARENA_MAX_CHUNK_SIZE").filter(|&size| size >= config.initial_size)
+        {
+            config.max_chunk_size = size;
+        }
+
+        if let Some(cutoff) = parse("ISTR_ARENA_LARGE_STRING_CUTOFF") {
+            config.large_string_cutoff = Some(cutoff);
+        }
+
+        config
+    }
+
+    /// The size of the first chunk of every arena
+    pub fn initial_size(mut self, size: usize) -> Self {
+        assert!(
+            (MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE).contains(&size),
+            "the initial chunk size must be between {MIN_CHUNK_SIZE} and {MAX_CHUNK_SIZE} bytes"
+        );
+        self.initial_size = size;
+        self.max_chunk_size = self.max_chunk_size.max(size);
+        self
+    }
+
+    /// Every new chunk is this many times larger than the last one, until it reaches the max
+    pub fn growth_factor(mut self, factor: usize) -> Self {
+        assert!(factor >= 1, "the growth factor must be at least 1");
+        self.growth_factor = factor;
+        self
+    }
+
+    /// The size chunks stop growing at
+    pub fn max_chunk_size(mut self, size: usize) -> Self {
+        assert!(
+            (self.initial_size..=MAX_CHUNK_SIZE).contains(&size),
+            "the max chunk size must be between the initial size and {MAX_CHUNK_SIZE} bytes"
+        );
+        self.max_chunk_size = size;
+        self
+    }
+
+    /// Strings that take up more than this many bytes get a dedicated allocation instead of
+    /// being placed in a chunk. By default this is twice the size of the arena's current chunk.
+    pub fn large_string_cutoff(mut self, cutoff: usize) -> Self {
+        self.large_string_cutoff = Some(cutoff);
+        self
+    }
+
+    /// The allocator that provides the chunks and large allocations
+    pub fn allocator(mut self, allocator: &'static dyn ChunkAllocator) -> Self {
+        self.allocator = allocator;
+        self
+    }
+
+    /// Makes this the config for every arena. Returns the config back if one was already
+    /// installed, or if a string was already allocated.
+    pub fn install(self) -> Result<(), Self> {
+        CONFIG.set(self)
+    }
+
+    /// The currently installed config
+    pub fn current() -> Self {
+        *get()
+    }
+
+    pub(crate) fn chunk_allocator(&self) -> &'static dyn ChunkAllocator {
+        self.allocator
+    }
+
+    pub(crate) fn first_chunk_size(&self) -> usize {
+        self.initial_size
+    }
+
+    pub(crate) fn next_chunk_size(&self, current: usize) -> usize {
+        current
+            .saturating_mul(self.growth_factor)
+            .min(self.max_chunk_size)
+    }
+
+    pub(crate) fn is_large(&self, size: usize, current_chunk_size: usize) -> bool {
+        match self.large_string_cutoff {
+            Some(cutoff) => size > cutoff,
+            None => size / 2 > current_chunk_size,
+        }
+    }
+}
+
+impl core::fmt::Debug for ArenaConfig {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ArenaConfig")
+            .field("initial_size", &self.initial_size)
+            .field("growth_factor", &self.growth_factor)
+            .field("max_chunk_size", &self.max_chunk_size)
+            .field("large_string_cutoff", &self.large_string_cutoff)
+            .finish_non_exhaustive()
+    }
+}
+
+// allocators are compared by address, since there's no other way to tell them apart
+impl PartialEq for ArenaConfig {
+    fn eq(&self, other: &Self) -> bool {
+        self.initial_size == other.initial_size
+            && self.growth_factor == other.growth_factor
+            && self.max_chunk_size == other.max_chunk_size
+            && self.large_string_cutoff == other.large_string_cutoff
+            && core::ptr::addr_eq(self.allocator, other.allocator)
+    }
+}
+
+impl Eq for ArenaConfig {}
+
+#[test]
+fn test_builder() {
+    let config = ArenaConfig::new()
+        .initial_size(4096)
+        .growth_factor(4)
+        .max_chunk_size(64 * 1024)
+        .large_string_cutoff(1024);
+
+    assert_eq!(config.first_chunk_size(), 4096);
+    assert_eq!(config.next_chunk_size(4096), 16 * 1024);
+    assert_eq!(config.next_chunk_size(32 * 1024), 64 * 1024);
+    assert!(config.is_large(1025, usize::MAX));
+    assert!(!config.is_large(1024, 0));
+
+    assert!(!ArenaConfig::new().is_large(2048, 1024));
+    assert!(ArenaConfig::new().is_large(2050, 1024));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_env() {
+    let config = ArenaConfig::from_vars(|name| match name {
+        "ISTR_ARENA_INITIAL_SIZE" => Some("65536".into()),
+        "ISTR_ARENA_GROWTH_FACTOR" => Some("0".into()),
+        "ISTR_ARENA_MAX_CHUNK_SIZE" => Some(" 1048576 ".into()),
+        "ISTR_ARENA_LARGE_STRING_CUTOFF" => Some("lots".into()),
+        _ => None,
+    });
+
+    assert_eq!(
+        config,
+        ArenaConfig::new()
+            .initial_size(65536)
+            .max_chunk_size(1024 * 1024)
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_env_out_of_range() {
+    let too_large = (MAX_CHUNK_SIZE + 1).to_string();
+
+    let config = ArenaConfig::from_vars(|name| match name {
+        "ISTR_ARENA_INITIAL_SIZE" | "ISTR_ARENA_MAX_CHUNK_SIZE" => Some(too_large.clone()),
+        _ => None,
+    });
+    assert_eq!(config, ArenaConfig::new());
+
+    // the largest allowed size must still produce a valid chunk layout
+    let config = ArenaConfig::from_vars(|name| match name {
+        "ISTR_ARENA_INITIAL_SIZE" => Some(MAX_CHUNK_SIZE.to_string()),
+        _ => None,
+    });
+    assert_eq!(config.first_chunk_size(), MAX_CHUNK_SIZE);
+    crate::leaky_alloc::chunk_layout(config.first_chunk_size());
+}
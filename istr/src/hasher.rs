@@ -1,20 +1,147 @@
-use std::hash::{BuildHasher, Hasher};
+// The hash function is picked by cargo feature. Since features are additive, if more than one
+// is enabled the first one in this order wins: xxh3, foldhash, fxhash, and ahash by default.
+//
+// Every string's hash is saved in its header and used directly by `IStrMap` and to pick a shard
+// in the global table, so the hash function must spread entropy across all 64 bits.
+//
+// With `random-seed` the seeds are read from the OS the first time a string is hashed, so
+// hashes are only consistent within a single process. The empty string is the exception, it
+// always hashes to `EMPTY_HASH` since the static empty string's header is built at compile time.
+//
+// The seed only protects against HashDoS if the hash function mixes it into every step. fxhash
+// just starts from it, so strings that collide with one seed collide with every seed, and
+// `random-seed` gives it no real protection.
 
-pub fn hash(value: &[u8]) -> u64 {
-    let mut hasher = ahash::RandomState::with_seeds(
-        3609252661711376574,
-        17522957641342131531,
-        18364184400384450343,
-        5674598519608203581,
-    )
-    .build_hasher();
+#[cfg(feature = "xxh3")]
+const DEFAULT_SEEDS: [u64; 4] = [0; 4];
+
+#[cfg(feature = "xxh3")]
+fn hash_with_seeds(value: &[u8], seeds: [u64; 4]) -> u64 {
+    xxhash_rust::xxh3::xxh3_64_with_seed(value, seeds[0])
+}
+
+#[cfg(feature = "xxh3")]
+pub const EMPTY_HASH: u64 = 3244421341483603138;
+
+#[cfg(all(feature = "foldhash", not(feature = "xxh3")))]
+const DEFAULT_SEEDS: [u64; 4] = [3609252661711376574, 0, 0, 0];
+
+#[cfg(all(feature = "foldhash", not(feature = "xxh3")))]
+fn hash_with_seeds(value: &[u8], seeds: [u64; 4]) -> u64 {
+    use core::hash::{BuildHasher, Hasher};
+
+    let mut hasher = foldhash::fast::FixedState::with_seed(seeds[0]).build_hasher();
+    hasher.write(value);
+    hasher.finish()
+}
+
+#[cfg(all(feature = "foldhash", not(feature = "xxh3")))]
+pub const EMPTY_HASH: u64 = 15069371259264581737;
+
+#[cfg(all(feature = "fxhash", not(any(feature = "xxh3", feature = "foldhash"))))]
+const DEFAULT_SEEDS: [u64; 4] = [0; 4];
+
+#[cfg(all(feature = "fxhash", not(any(feature = "xxh3", feature = "foldhash"))))]
+fn hash_with_seeds(value: &[u8], seeds: [u64; 4]) -> u64 {
+    use core::hash::Hasher;
+
+    let mut hasher = rustc_hash::FxHasher::with_seed(seeds[0] as usize);
+    hasher.write(value);
+    hasher.finish()
+}
+
+#[cfg(all(feature = "fxhash", not(any(feature = "xxh3", feature = "foldhash"))))]
+pub const EMPTY_HASH: u64 = 17606491139363777937;
+
+#[cfg(not(any(feature = "xxh3", feature = "foldhash", feature = "fxhash")))]
+const DEFAULT_SEEDS: [u64; 4] = [
+    3609252661711376574,
+    17522957641342131531,
+    18364184400384450343,
+    5674598519608203581,
+];
+
+#[cfg(not(any(feature = "xxh3", feature = "foldhash", feature = "fxhash")))]
+fn hash_with_seeds(value: &[u8], seeds: [u64; 4]) -> u64 {
+    use core::hash::{BuildHasher, Hasher};
+
+    let mut hasher =
+        ahash::RandomState::with_seeds(seeds[0], seeds[1], seeds[2], seeds[3]).build_hasher();
     hasher.write(value);
     hasher.finish()
 }
 
+#[cfg(not(any(feature = "xxh3", feature = "foldhash", feature = "fxhash")))]
 pub const EMPTY_HASH: u64 = 180362161520211164;
 
+#[cfg(not(feature = "random-seed"))]
+#[inline]
+fn seeds() -> [u64; 4] {
+    DEFAULT_SEEDS
+}
+
+#[cfg(feature = "random-seed")]
+#[inline]
+fn seeds() -> [u64; 4] {
+    static SEEDS: std::sync::OnceLock<[u64; 4]> = std::sync::OnceLock::new();
+
+    *SEEDS.get_or_init(|| {
+        let mut bytes = [0; 32];
+        getrandom::getrandom(&mut bytes).expect("Could not seed the string hasher");
+
+        let mut seeds = [0; 4];
+        for (seed, bytes) in seeds.iter_mut().zip(bytes.chunks_exact(8)) {
+            *seed = u64::from_le_bytes(bytes.try_into().unwrap());
+        }
+        seeds
+    })
+}
+
+// the same on every run, even with `random-seed`
+#[cfg(feature = "std")]
+pub(crate) fn unseeded_hash(value: &[u8]) -> u64 {
+    hash_with_seeds(value, DEFAULT_SEEDS)
+}
+
+pub fn hash(value: &[u8]) -> u64 {
+    if cfg!(feature = "random-seed") && value.is_empty() {
+        return EMPTY_HASH;
+    }
+
+    hash_with_seeds(value, seeds())
+}
+
 #[test]
 fn test() {
-    assert_eq!(hash(b""), EMPTY_HASH)
+    assert_eq!(hash(b""), EMPTY_HASH);
+    assert_eq!(hash_with_seeds(b"", DEFAULT_SEEDS), EMPTY_HASH);
+}
+
+#[cfg(feature = "random-seed")]
+#[test]
+fn test_random_seed() {
+    assert_ne!(seeds(), DEFAULT_SEEDS);
+    assert_eq!(hash(b"seeded"), hash(b"seeded"));
+    assert_ne!(hash(b"seeded"), hash_with_seeds(b"seeded", DEFAULT_SEEDS));
+}
+
+#[test]
+fn test_spread() {
+    // short strings have to land in most of the shards, and most of the buckets of a small map
+    let hashes = (0..1024)
+        .map(|i| hash(format!("id{i}").as_bytes()))
+        .collect::<Vec<_>>();
+
+    let mut shards = hashes
+        .iter()
+        .map(|&hash| crate::table::shard_index(hash, 64))
+        .collect::<Vec<_>>();
+    shards.sort_unstable();
+    shards.dedup();
+    assert!(shards.len() > 56);
+
+    let mut buckets = hashes.iter().map(|&hash| hash & 255).collect::<Vec<_>>();
+    buckets.sort_unstable();
+    buckets.dedup();
+    assert!(buckets.len() > 224);
 }
@@ -0,0 +1,284 @@
+use core::{ffi::CStr, marker::PhantomData, ops::Deref, str::Utf8Error};
+
+use crate::{hasher, leaky_alloc, sync::Mutex, table::ShardedTable};
+
+// An interner with its own tables and arena, unlike the global interner all of the strings
+// are deallocated once the interner is dropped. The handles borrow the interner, so they
+// can't outlive it.
+pub struct Interner {
+    table: ShardedTable,
+    arena: Mutex<leaky_alloc::Arena>,
+}
+
+#[repr(transparent)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct IBytes<'a> {
+    bytes: crate::IBytes,
+    interner: PhantomData<&'a Interner>,
+}
+
+#[repr(transparent)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct IStr<'a>(IBytes<'a>);
+
+impl nohash_hasher::IsEnabled for IStr<'_> {}
+impl nohash_hasher::IsEnabled for IBytes<'_> {}
+
+impl Default for Interner {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Interner {
+    fn drop(&mut self) {
+        let arena = self.arena.get_mut();
+
+        // SAFETY: every handle borrows the interner, so none of them can be alive here
+        unsafe { arena.dealloc_all() }
+    }
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self {
+            table: ShardedTable::new(),
+            arena: Mutex::new(leaky_alloc::Arena::new()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn intern(&self, s: &[u8], guaranteed_valid_utf8: bool) -> crate::IBytes {
+        let hash = hasher::hash(s);
+
+        self.table.get_or_insert_with(s, hash, || {
+            let arena = &mut *self.arena.lock();
+            leaky_alloc::with_hash_bytes_in(arena, s, hash, guaranteed_valid_utf8)
+        })
+    }
+
+    fn get(&self, s: &[u8]) -> Option<crate::IBytes> {
+        self.table.get(s, hasher::hash(s))
+    }
+}
+
+impl<'a> IBytes<'a> {
+    #[inline]
+    fn from_raw(bytes: crate::IBytes) -> Self {
+        Self {
+            bytes,
+            interner: PhantomData,
+        }
+    }
+
+    #[inline]
+    pub fn empty() -> Self {
+        Self::from_raw(crate::IBytes::empty())
+    }
+
+    #[inline]
+    pub fn new_in(s: &[u8], interner: &'a Interner) -> Self {
+        Self::from_raw(interner.intern(s, false))
+    }
+
+    #[inline]
+    pub fn get_in(s: &[u8], interner: &'a Interner) -> Option<Self> {
+        interner.get(s).map(Self::from_raw)
+    }
+
+    #[inline]
+    pub fn to_bytes(self) -> &'a [u8] {
+        self.bytes.to_bytes()
+    }
+
+    #[inline]
+    pub fn len(self) -> usize {
+        self.bytes.len()
+    }
+
+    #[inline]
+    pub fn is_empty(self) -> bool {
+        self.len() == 0
+    }
+
+    #[inline]
+    pub fn saved_hash(self) -> u64 {
+        self.bytes.saved_hash()
+    }
+
+    #[inline]
+    pub fn as_cstr_ptr(self) -> *const core::ffi::c_char {
+        self.bytes.as_cstr_ptr()
+    }
+
+    #[inline]
+    pub fn as_cstr(self) -> &'a CStr {
+        self.bytes.as_cstr()
+    }
+}
+
+impl<'a> IStr<'a> {
+    #[inline]
+    pub fn empty() -> Self {
+        Self(IBytes::empty())
+    }
+
+    #[inline]
+    pub fn new_in(s: &str, interner: &'a Interner) -> Self {
+        Self(IBytes::from_raw(interner.intern(s.as_bytes(), true)))
+    }
+
+    #[inline]
+    pub fn get_in(s: &str, interner: &'a Interner) -> Option<Self> {
+        IBytes::get_in(s.as_bytes(), interner).map(Self)
+    }
+
+    #[inline]
+    pub fn from_utf8(bytes: IBytes<'a>) -> Result<Self, Utf8Error> {
+        crate::IStr::from_utf8(bytes.bytes)?;
+        Ok(Self(bytes))
+    }
+
+    #[inline]
+    pub fn to_str(self) -> &'a str {
+        unsafe { core::str::from_utf8_unchecked(self.to_bytes()) }
+    }
+
+    #[inline]
+    pub fn to_bytes(self) -> &'a [u8] {
+        self.0.to_bytes()
+    }
+
+    #[inline]
+    pub fn to_ibytes(self) -> IBytes<'a> {
+        self.0
+    }
+
+    #[inline]
+    pub fn len(self) -> usize {
+        self.0.len()
+    }
+
+    #[inline]
+    pub fn is_empty(self) -> bool {
+        self.len() == 0
+    }
+
+    #[inline]
+    pub fn saved_hash(self) -> u64 {
+        self.0.saved_hash()
+    }
+
+    #[inline]
+    pub fn as_cstr_ptr(self) -> *const core::ffi::c_char {
+        self.0.as_cstr_ptr()
+    }
+
+    #[inline]
+    pub fn as_cstr(self) -> &'a CStr {
+        self.0.as_cstr()
+    }
+}
+
+impl Default for IBytes<'_> {
+    #[inline]
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl Default for IStr<'_> {
+    #[inline]
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl<'a> From<IStr<'a>> for IBytes<'a> {
+    #[inline]
+    fn from(value: IStr<'a>) -> Self {
+        value.0
+    }
+}
+
+impl Deref for IStr<'_> {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        self.to_str()
+    }
+}
+
+impl core::fmt::Debug for IBytes<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.to_bytes().fmt(f)
+    }
+}
+
+impl core::fmt::Pointer for IBytes<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.bytes.fmt(f)
+    }
+}
+
+impl core::fmt::Debug for IStr<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.to_str().fmt(f)
+    }
+}
+
+impl core::fmt::Display for IStr<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.to_str().fmt(f)
+    }
+}
+
+impl core::fmt::Pointer for IStr<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[test]
+fn test_simple() {
+    let interner = Interner::new();
+    assert_eq!(
+        IStr::new_in("hello", &interner),
+        IStr::new_in("hello", &interner)
+    );
+    assert_eq!(interner.len(), 1);
+}
+
+#[test]
+fn test_separate_from_global() {
+    let interner = Interner::new();
+    let local = IStr::new_in("hello interner", &interner);
+    let global = crate::IStr::new("hello interner");
+    assert_ne!(local.as_cstr_ptr(), global.as_cstr_ptr());
+    assert_eq!(IStr::get_in("hello interner", &interner), Some(local));
+    assert_eq!(IStr::get_in("missing", &interner), None);
+}
+
+#[test]
+fn test_large_string() {
+    let large = include_str!("../../fixtures/large_string.txt");
+    let interner = Interner::new();
+    assert_eq!(
+        IStr::new_in(large, &interner),
+        IStr::new_in(large, &interner)
+    );
+    for i in 0..4096 {
+        IStr::new_in(&i.to_string(), &interner);
+    }
+    // larger than the initial chunk, so it gets a dedicated allocation
+    let huge = "a".repeat(4 * 1024 * 1024);
+    assert_eq!(IStr::new_in(&huge, &interner).to_str(), huge);
+}
@@ -0,0 +1,73 @@
+use core::{
+    ptr::NonNull,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use crate::{IBytes, IStr};
+
+// An `IStr` that is interned on first use, after that getting it is a single atomic load
+pub struct LazyIStr {
+    value: &'static str,
+    istr: AtomicPtr<u8>,
+}
+
+#[macro_export]
+macro_rules! istr {
+    ($s:expr) => {{
+        static ISTR: $crate::LazyIStr = $crate::LazyIStr::new($s);
+        ISTR.get()
+    }};
+}
+
+impl LazyIStr {
+    pub const fn new(value: &'static str) -> Self {
+        Self {
+            value,
+            istr: AtomicPtr::new(core::ptr::null_mut()),
+        }
+    }
+
+    #[inline]
+    pub fn get(&self) -> IStr {
+        match NonNull::new(self.istr.load(Ordering::Acquire)) {
+            Some(ptr) => unsafe { IStr::from_utf8_unchecked(IBytes::from_ptr(ptr)) },
+            None => self.init(),
+        }
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn init(&self) -> IStr {
+        // racing initializations all intern the same string, so they store the same pointer
+        let istr = IStr::new(self.value);
+        self.istr
+            .store(istr.to_ibytes().as_ptr().as_ptr(), Ordering::Release);
+        istr
+    }
+}
+
+impl core::fmt::Debug for LazyIStr {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.value.fmt(f)
+    }
+}
+
+#[test]
+fn test_macro() {
+    let get = || istr!("lazy self");
+
+    assert_eq!(get(), IStr::new("lazy self"));
+    assert_eq!(get(), get());
+}
+
+#[cfg(not(feature = "single-threaded"))]
+#[test]
+fn test_static() {
+    static KW_SELF: LazyIStr = LazyIStr::new("lazy static self");
+
+    std::thread::scope(|s| {
+        for _ in 0..4 {
+            s.spawn(|| assert_eq!(KW_SELF.get(), IStr::new("lazy static self")));
+        }
+    });
+}
@@ -1,46 +1,95 @@
-use std::{
-    alloc::Layout, cell::Cell, ffi::CStr, hash::Hash, mem::MaybeUninit, ops::Deref, ptr::NonNull,
+use alloc::{alloc::Layout, vec::Vec};
+use core::{
+    cell::UnsafeCell, ffi::CStr, hash::Hash, mem::MaybeUninit, ops::Deref, ptr::NonNull,
     str::Utf8Error,
 };
 
-// start of with a megabyte of storage, this should usualy be all that's needed
-// for the entire program, and usually there shouldn't be any strings larger than
-// a megabyte
-const INITIAL_SIZE: usize = 1024 * 1024;
+// chunks with less space than this left when their thread exits aren't worth recycling
+#[cfg(all(feature = "std", not(feature = "single-threaded")))]
+const MIN_RECYCLE_SIZE: usize = 4 * 1024;
 
-const ALIGN: usize = std::mem::align_of::<InternedStringHeader>();
+const ALIGN: usize = core::mem::align_of::<InternedStringHeader>();
+
+// the hash of a `static_istr!` string until it's registered, the assertion in `saved_hash` has to
+// rehash strings that really hash to this, so it should be unlikely
+const UNREGISTERED_HASH: u64 = 0;
 const ALIGN_MASK: usize = !ALIGN.wrapping_sub(1);
 
-#[cfg(miri)]
-use std::sync::{Mutex, PoisonError};
+use crate::{
+    config::{self, ArenaConfig, ChunkAllocator},
+    stats::{self, MemoryStats, StatsSlot},
+};
+
+#[cfg(feature = "single-threaded")]
+use crate::sync::Exclusive;
+#[cfg(not(feature = "single-threaded"))]
+use crate::sync::Mutex;
 
-#[cfg(miri)]
+#[cfg(feature = "symbol")]
+use core::sync::atomic::AtomicU32;
+
+#[cfg(all(miri, feature = "std", not(feature = "single-threaded")))]
 static LEAKED_MEMORY: Mutex<Vec<FrozenLeakyAllocPtr>> = Mutex::new(Vec::new());
 
+#[cfg(all(miri, feature = "std", not(feature = "single-threaded")))]
 struct FrozenLeakyAllocPtr(*mut ());
 
+#[cfg(all(miri, feature = "std", not(feature = "single-threaded")))]
 unsafe impl Send for FrozenLeakyAllocPtr {}
+#[cfg(all(miri, feature = "std", not(feature = "single-threaded")))]
 unsafe impl Sync for FrozenLeakyAllocPtr {}
 
-#[derive(Clone)]
-struct LeakyAllocHandle(*mut LeakyAlloc);
+#[cfg(all(feature = "std", not(feature = "single-threaded")))]
+struct LeakyAllocHandle(UnsafeCell<Arena>);
 
+#[cfg(all(feature = "std", not(feature = "single-threaded")))]
 thread_local! {
-    static ALLOC: Cell<LeakyAllocHandle> = Cell::new(LeakyAllocHandle(LeakyAlloc::new_::<true>()))
+    static ALLOC: LeakyAllocHandle = const { LeakyAllocHandle(UnsafeCell::new(Arena::new_per_thread())) }
 }
 
-#[cfg(miri)]
+// When a thread exits, the rest of its current chunk is handed to the next thread that needs
+// one, instead of being stranded
+#[cfg(all(feature = "std", not(feature = "single-threaded")))]
+static RECYCLED: Mutex<Vec<RecycledChunk>> = Mutex::new(Vec::new());
+
+#[cfg(all(feature = "std", not(feature = "single-threaded")))]
+struct RecycledChunk(*mut LeakyAlloc);
+
+#[cfg(all(feature = "std", not(feature = "single-threaded")))]
+unsafe impl Send for RecycledChunk {}
+
+// With `std` this is used for strings that are interned from a thread-local destructor after
+// `ALLOC` was destroyed, without it there are no threads, so every string is allocated here
+#[cfg(not(feature = "single-threaded"))]
+static GLOBAL: Mutex<Arena> = Mutex::new(Arena::new_recycling());
+
+// with `single-threaded` every string is allocated here, without going through a thread-local
+#[cfg(feature = "single-threaded")]
+static GLOBAL: Exclusive<Arena> = Exclusive::new(Arena::new());
+
+#[cfg(all(miri, feature = "std", not(feature = "single-threaded")))]
 fn register_leaked(ptr: *mut ()) {
-    let guard = &mut *LEAKED_MEMORY.lock().unwrap_or_else(PoisonError::into_inner);
+    let guard = &mut *LEAKED_MEMORY.lock();
 
     // this is done to get around miri's leak check
     guard.push(FrozenLeakyAllocPtr(ptr));
 }
 
-#[cfg(miri)]
+#[cfg(all(feature = "std", not(feature = "single-threaded")))]
 impl Drop for LeakyAllocHandle {
     fn drop(&mut self) {
-        register_leaked(self.0.cast())
+        let arena = self.0.get_mut();
+
+        arena.retire(&mut RECYCLED.lock());
+
+        #[cfg(miri)]
+        {
+            register_leaked(arena.chunk.cast());
+            arena
+                .large
+                .iter()
+                .for_each(|&(ptr, _)| register_leaked(ptr.cast()));
+        }
     }
 }
 
@@ -53,35 +102,186 @@ pub struct LeakyAlloc {
     data: [u8; 0],
 }
 
-fn get_alloc() -> *mut LeakyAlloc {
-    ALLOC.with(|alloc| unsafe { (*alloc.as_ptr()).0 })
+// A chain of `LeakyAlloc` chunks along with any dedicated allocations for large strings.
+//
+// The per-thread arenas behind `ALLOC` and `GLOBAL` are never deallocated, every other arena is
+// owned by an `Interner` which frees it once all of its strings are unreachable.
+pub(crate) struct Arena {
+    chunk: *mut LeakyAlloc,
+    large: Vec<(*mut u8, Layout)>,
+    stats: MemoryStats,
+    // where the stats are published, registered when the arena first allocates
+    slot: Option<&'static StatsSlot>,
+    per_thread: bool,
+    // only arenas that are never deallocated may adopt recycled chunks
+    #[cfg_attr(
+        any(not(feature = "std"), feature = "single-threaded"),
+        allow(dead_code)
+    )]
+    recycle: bool,
+    // the allocator that all of the chunks and large allocations came from
+    allocator: Option<&'static dyn ChunkAllocator>,
+}
+
+unsafe impl Send for Arena {}
+
+#[cfg(all(feature = "std", not(feature = "single-threaded")))]
+fn with_alloc<O>(f: impl FnOnce(&mut Arena) -> O) -> O {
+    let mut f = Some(f);
+
+    if let Ok(output) = ALLOC.try_with(|alloc| (f.take().unwrap())(unsafe { &mut *alloc.0.get() }))
+    {
+        return output;
+    }
+
+    (f.take().unwrap())(&mut GLOBAL.lock())
+}
+
+#[cfg(not(any(feature = "std", feature = "single-threaded")))]
+fn with_alloc<O>(f: impl FnOnce(&mut Arena) -> O) -> O {
+    f(&mut GLOBAL.lock())
+}
+
+#[cfg(feature = "single-threaded")]
+fn with_alloc<O>(f: impl FnOnce(&mut Arena) -> O) -> O {
+    GLOBAL.with(f)
+}
+
+pub(crate) fn local_stats() -> MemoryStats {
+    with_alloc(|arena| arena.stats)
+}
+
+pub(crate) fn publish_local() {
+    with_alloc(|arena| {
+        if let Some(slot) = arena.slot {
+            slot.publish(&arena.stats)
+        }
+    })
 }
 
 impl LeakyAlloc {
-    #[cold]
-    fn new() -> *mut LeakyAlloc {
-        Self::new_::<false>()
+    unsafe fn remaining(ptr: *mut LeakyAlloc) -> usize {
+        let start = core::ptr::addr_of!((*ptr).data).cast::<u8>();
+        (*ptr).ptr.offset_from(start) as usize
+    }
+}
+
+impl Arena {
+    pub(crate) const fn new() -> Self {
+        Self::with_recycle(false, false)
+    }
+
+    #[cfg(not(feature = "single-threaded"))]
+    const fn new_recycling() -> Self {
+        Self::with_recycle(true, false)
+    }
+
+    #[cfg(all(feature = "std", not(feature = "single-threaded")))]
+    const fn new_per_thread() -> Self {
+        Self::with_recycle(true, true)
+    }
+
+    const fn with_recycle(recycle: bool, per_thread: bool) -> Self {
+        Self {
+            chunk: core::ptr::null_mut(),
+            large: Vec::new(),
+            stats: MemoryStats {
+                arenas: 0,
+                chunks: 0,
+                reserved_bytes: 0,
+                used_bytes: 0,
+                padding_bytes: 0,
+                large_allocs: 0,
+                large_bytes: 0,
+                stranded_bytes: 0,
+            },
+            slot: None,
+            per_thread,
+            recycle,
+            allocator: None,
+        }
+    }
+
+    fn publish(&mut self) {
+        self.stats.arenas = 1;
+        let slot = *self
+            .slot
+            .get_or_insert_with(|| stats::register(self.per_thread));
+        slot.publish(&self.stats);
+    }
+
+    // hands the current chunk over to `recycled`, the arena must not be used afterwards
+    #[cfg(all(feature = "std", not(feature = "single-threaded")))]
+    fn retire(&mut self, recycled: &mut Vec<RecycledChunk>) {
+        if self.chunk.is_null() {
+            return;
+        }
+
+        let remaining = unsafe { LeakyAlloc::remaining(self.chunk) };
+
+        if remaining >= MIN_RECYCLE_SIZE {
+            // the free part of the chunk is counted by the arena that adopts it
+            self.stats.chunks -= 1;
+            self.stats.reserved_bytes -= remaining;
+            recycled.push(RecycledChunk(self.chunk));
+        } else {
+            self.stats.stranded_bytes += remaining;
+        }
+
+        self.publish();
+    }
+
+    #[cfg(all(feature = "std", not(feature = "single-threaded")))]
+    fn adopt(&mut self, recycled: &mut Vec<RecycledChunk>) -> Option<*mut LeakyAlloc> {
+        let RecycledChunk(chunk) = recycled.pop()?;
+
+        self.stats.chunks += 1;
+        self.stats.reserved_bytes += unsafe { LeakyAlloc::remaining(chunk) };
+        self.publish();
+
+        self.chunk = chunk;
+        Some(chunk)
+    }
+
+    fn allocate(&mut self, layout: Layout, config: &ArenaConfig) -> *mut u8 {
+        let allocator = *self.allocator.get_or_insert(config.chunk_allocator());
+        let ptr = allocator.alloc(layout);
+
+        if ptr.is_null() {
+            alloc::alloc::handle_alloc_error(layout)
+        }
+
+        ptr
     }
 
     #[cold]
-    fn new_<const FIRST: bool>() -> *mut LeakyAlloc {
-        let (layout, prev) = if FIRST {
-            (
-                Layout::from_size_align(INITIAL_SIZE, 16).unwrap(),
-                core::ptr::null_mut(),
-            )
+    fn grow(&mut self, config: &ArenaConfig) -> *mut LeakyAlloc {
+        #[cfg(all(feature = "std", not(feature = "single-threaded")))]
+        if self.chunk.is_null() && self.recycle {
+            let recycled = &mut *RECYCLED.lock();
+
+            if let Some(chunk) = self.adopt(recycled) {
+                return chunk;
+            }
+        }
+
+        let (size, prev) = if self.chunk.is_null() {
+            (config.first_chunk_size(), core::ptr::null_mut())
         } else {
-            let prev_ptr = get_alloc();
-            let prev = unsafe { &*prev_ptr };
-            (prev.layout.extend(prev.layout).unwrap().0, prev_ptr)
+            let prev = unsafe { &*self.chunk };
+            (config.next_chunk_size(prev.layout.size()), self.chunk)
         };
 
-        let ptr = unsafe { std::alloc::alloc(layout) };
+        let layout = chunk_layout(size);
 
-        if ptr.is_null() {
-            std::alloc::handle_alloc_error(layout)
+        self.stats.chunks += 1;
+        self.stats.reserved_bytes += layout.size();
+        if !prev.is_null() {
+            self.stats.stranded_bytes += unsafe { LeakyAlloc::remaining(prev) };
         }
+        self.publish();
 
+        let ptr = self.allocate(layout, config);
         let end = unsafe { ptr.add(layout.size()) };
         let ptr = ptr.cast::<LeakyAlloc>();
 
@@ -94,79 +294,135 @@ impl LeakyAlloc {
             })
         }
 
-        if !FIRST {
-            ALLOC.with(|alloc| alloc.set(LeakyAllocHandle(ptr)));
-        }
+        self.chunk = ptr;
 
         ptr
     }
-}
 
-#[cold]
-#[inline(never)]
-fn large_alloc(size: usize) -> *mut u8 {
-    // super large string, just give it a dedicated allocation
+    #[cold]
+    #[inline(never)]
+    fn large_alloc(&mut self, size: usize, config: &ArenaConfig) -> *mut u8 {
+        // super large string, just give it a dedicated allocation
 
-    let layout = Layout::from_size_align(size, ALIGN).unwrap();
+        let layout = Layout::from_size_align(size, ALIGN).unwrap();
+        let ptr = self.allocate(layout, config);
 
-    let ptr = unsafe { std::alloc::alloc(layout) };
+        self.large.push((ptr, layout));
+        self.stats.reserved_bytes += size;
+        self.stats.used_bytes += size;
+        self.stats.large_allocs += 1;
+        self.stats.large_bytes += size;
+        self.publish();
 
-    if ptr.is_null() {
-        std::alloc::handle_alloc_error(layout)
+        ptr
     }
 
-    #[cfg(miri)]
-    register_leaked(ptr.cast());
+    fn alloc(&mut self, size: usize) -> *mut u8 {
+        self.alloc_with(size, config::get())
+    }
 
-    ptr
-}
+    fn alloc_with(&mut self, size: usize, config: &ArenaConfig) -> *mut u8 {
+        let mut ptr = self.chunk;
 
-fn alloc(size: usize) -> *mut u8 {
-    let mut ptr = get_alloc();
+        if ptr.is_null() {
+            ptr = self.grow(config);
+        }
 
-    let mut start = unsafe { core::ptr::addr_of!((*ptr).data).cast::<u8>() };
-    let mut header = unsafe { &mut *ptr };
+        let mut start = unsafe { core::ptr::addr_of!((*ptr).data).cast::<u8>() };
+        let mut header = unsafe { &mut *ptr };
 
-    let remaining = unsafe { header.ptr.offset_from(start) as usize };
+        let remaining = unsafe { header.ptr.offset_from(start) as usize };
 
-    debug_assert_eq!(remaining % ALIGN, 0);
+        debug_assert_eq!(remaining % ALIGN, 0);
 
-    if remaining >= size {
-        // already enough space
-    } else if header.layout.size() >= size / 2 {
-        // create a new leaky alloc, since it is guaranteed to be larger than the string
+        let large = config.is_large(size, header.layout.size());
 
-        ptr = LeakyAlloc::new();
+        if !large && remaining >= size {
+            // already enough space
+        } else if !large
+            && chunk_capacity(chunk_layout(config.next_chunk_size(header.layout.size()))) >= size
+        {
+            // create a new leaky alloc, since it is large enough to hold the string
 
-        start = unsafe { core::ptr::addr_of!((*ptr).data).cast::<u8>() };
-        header = unsafe { &mut *ptr };
-    } else {
-        // for a very large allocation, just create a new allocation dedicated to the string
+            ptr = self.grow(config);
 
-        return large_alloc(size);
+            start = unsafe { core::ptr::addr_of!((*ptr).data).cast::<u8>() };
+            header = unsafe { &mut *ptr };
+        } else {
+            // for a very large allocation, just create a new allocation dedicated to the string
+
+            return self.large_alloc(size, config);
+        }
+
+        // if we have enough space in the current leaky alloc, cut off enough space for the string
+        // this operates as a bump allocator where the allocator grows down the address space
+        // https://fitzgeraldnick.com/2019/11/01/always-bump-downwards.html
+
+        let current = unsafe { header.ptr.sub(size) };
+        #[allow(clippy::transmutes_expressible_as_ptr_casts)]
+        let current_addr = unsafe { core::mem::transmute::<*mut u8, usize>(current) };
+        let addr = current_addr & ALIGN_MASK;
+        let current = unsafe { current.sub(current_addr - addr) };
+        header.ptr = current;
+
+        self.stats.used_bytes += size;
+        self.stats.padding_bytes += current_addr - addr;
+
+        debug_assert!(current as *const u8 >= start);
+        debug_assert!(
+            current as *const u8 <= unsafe { (ptr as *const u8).add(header.layout.size()) }
+        );
+
+        current
     }
 
-    // if we have enough space in the current leaky alloc, cut off enough space for the string
-    // this operates as a bump allocator where the allocator grows down the address space
-    // https://fitzgeraldnick.com/2019/11/01/always-bump-downwards.html
+    /// # Safety
+    ///
+    /// None of the strings allocated from this arena may be used after this call
+    pub(crate) unsafe fn dealloc_all(&mut self) {
+        let mut chunk = core::mem::replace(&mut self.chunk, core::ptr::null_mut());
+        let Some(allocator) = self.allocator else {
+            return;
+        };
+
+        while !chunk.is_null() {
+            let LeakyAlloc { layout, prev, .. } = chunk.read();
+            allocator.dealloc(chunk.cast(), layout);
+            chunk = prev;
+        }
+
+        for (ptr, layout) in self.large.drain(..) {
+            allocator.dealloc(ptr, layout);
+        }
 
-    let current = unsafe { header.ptr.sub(size) };
-    #[allow(clippy::transmutes_expressible_as_ptr_casts)]
-    let current_addr = unsafe { core::mem::transmute::<_, usize>(current) };
-    let addr = current_addr & ALIGN_MASK;
-    let current = unsafe { current.sub(current_addr - addr) };
-    header.ptr = current;
+        self.stats = MemoryStats::default();
+
+        if let Some(slot) = self.slot.take() {
+            stats::unregister(slot);
+        }
+    }
+}
 
-    debug_assert!(current as *const u8 >= start);
-    debug_assert!(current as *const u8 <= unsafe { (ptr as *const u8).add(header.layout.size()) });
+pub(crate) fn chunk_layout(size: usize) -> Layout {
+    Layout::from_size_align(size, 16)
+        .expect("Overflow while calculating layout")
+        .pad_to_align()
+}
 
-    current
+// the largest string allocation that fits in a new chunk with this layout
+fn chunk_capacity(layout: Layout) -> usize {
+    layout
+        .size()
+        .saturating_sub(core::mem::size_of::<LeakyAlloc>())
+        & ALIGN_MASK
 }
 
 #[repr(C)]
 pub(crate) struct InternedStringHeader {
     hash: u64,
     len: usize,
+    #[cfg(feature = "symbol")]
+    symbol: AtomicU32,
     #[cfg(feature = "cache-utf8")]
     is_valid_utf8: bool,
     data: [u8; 0],
@@ -176,18 +432,81 @@ pub(crate) struct InternedStringHeader {
 pub(crate) struct InternedStringData<const N: usize> {
     hash: u64,
     len: usize,
+    #[cfg(feature = "symbol")]
+    symbol: AtomicU32,
     #[cfg(feature = "cache-utf8")]
     is_valid_utf8: bool,
     data: [u8; N],
 }
 
+// Backing storage for `static_istr!`, the hash isn't known at compile time so it is filled in
+// by `register_static`
+#[doc(hidden)]
 #[repr(transparent)]
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct StaticIStrData<const N: usize>(UnsafeCell<InternedStringData<N>>);
+
+unsafe impl<const N: usize> Sync for StaticIStrData<N> {}
+
+impl<const N: usize> StaticIStrData<N> {
+    pub const fn new(s: &str) -> Self {
+        let bytes = s.as_bytes();
+        assert!(bytes.len() + 1 == N);
+        // there is only one empty string, and it's not static
+        assert!(
+            !bytes.is_empty(),
+            "static_istr! can't be used with an empty string, use IStr::empty() instead"
+        );
+
+        // the last byte is left as the nul terminator
+        let mut data = [0; N];
+        let mut i = 0;
+        while i < bytes.len() {
+            data[i] = bytes[i];
+            i += 1;
+        }
+
+        Self(UnsafeCell::new(InternedStringData {
+            hash: UNREGISTERED_HASH,
+            len: bytes.len(),
+            #[cfg(feature = "symbol")]
+            symbol: AtomicU32::new(0),
+            #[cfg(feature = "cache-utf8")]
+            is_valid_utf8: true,
+            data,
+        }))
+    }
+
+    pub const fn istr(&'static self) -> IStr {
+        let data = unsafe { core::ptr::addr_of_mut!((*self.0.get()).data) };
+        unsafe { IStr::from_utf8_unchecked(IBytes(NonNull::new_unchecked(data.cast()))) }
+    }
+}
+
+// Ordered by address, unless `lex-ord` is enabled. See `LexOrd` for ordering by contents.
+#[repr(transparent)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(not(feature = "lex-ord"), derive(PartialOrd, Ord))]
 pub struct IBytes(NonNull<u8>);
 
+#[cfg(feature = "lex-ord")]
+impl Ord for IBytes {
+    #[inline]
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        crate::lex_ord::cmp_bytes(*self, *other)
+    }
+}
+
+#[cfg(feature = "lex-ord")]
+impl PartialOrd for IBytes {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl Hash for IBytes {
     #[inline]
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
         self.saved_hash().hash(state)
     }
 }
@@ -210,37 +529,60 @@ pub(crate) fn with_hash(s: &str, hash: u64) -> IStr {
     unsafe { IStr::from_utf8_unchecked(bytes) }
 }
 
-pub(crate) fn with_hash_bytes(s: &[u8], hash: u64, _guaranteed_valid_utf8: bool) -> IBytes {
+pub(crate) fn with_hash_bytes(s: &[u8], hash: u64, guaranteed_valid_utf8: bool) -> IBytes {
+    with_alloc(|arena| with_hash_bytes_in(arena, s, hash, guaranteed_valid_utf8))
+}
+
+pub(crate) fn with_hash_bytes_in(
+    arena: &mut Arena,
+    s: &[u8],
+    hash: u64,
+    _guaranteed_valid_utf8: bool,
+) -> IBytes {
     if s.is_empty() {
         return IBytes::empty();
     }
 
-    const HEADER_PLUS_NUL_TERM: usize =
-        core::mem::size_of::<u64>() + core::mem::size_of::<usize>() + 1;
     let size = HEADER_PLUS_NUL_TERM
         .checked_add(s.len())
         .expect("Overflow while calculating layout");
 
-    let ptr = alloc(size).cast::<InternedStringHeader>();
+    let ptr = arena.alloc(size).cast::<InternedStringHeader>();
 
-    unsafe {
-        ptr.write(InternedStringHeader {
-            hash,
-            len: s.len(),
-            #[cfg(feature = "cache-utf8")]
-            is_valid_utf8: _guaranteed_valid_utf8 || simdutf8::basic::from_utf8(s).is_ok(),
-            data: [],
-        });
-
-        let ptr = core::ptr::addr_of_mut!((*ptr).data).cast::<u8>();
-
-        ptr.copy_from_nonoverlapping(s.as_ptr(), s.len());
+    unsafe { write_string(ptr, s, hash, _guaranteed_valid_utf8) }
+}
 
-        // add a nul terminator, to ensure that every string is a valid cstr
-        ptr.add(s.len()).write(0);
+pub(crate) const HEADER_PLUS_NUL_TERM: usize =
+    core::mem::offset_of!(InternedStringHeader, data) + 1;
 
-        IBytes(NonNull::new_unchecked(ptr))
-    }
+/// # Safety
+///
+/// `ptr` must be aligned, and valid for writes of `HEADER_PLUS_NUL_TERM + s.len()` bytes
+/// rounded up to the alignment of `InternedStringHeader`
+pub(crate) unsafe fn write_string(
+    ptr: *mut InternedStringHeader,
+    s: &[u8],
+    hash: u64,
+    _guaranteed_valid_utf8: bool,
+) -> IBytes {
+    ptr.write(InternedStringHeader {
+        hash,
+        len: s.len(),
+        #[cfg(feature = "symbol")]
+        symbol: AtomicU32::new(0),
+        #[cfg(feature = "cache-utf8")]
+        is_valid_utf8: _guaranteed_valid_utf8 || simdutf8::basic::from_utf8(s).is_ok(),
+        data: [],
+    });
+
+    let ptr = core::ptr::addr_of_mut!((*ptr).data).cast::<u8>();
+
+    ptr.copy_from_nonoverlapping(s.as_ptr(), s.len());
+
+    // add a nul terminator, to ensure that every string is a valid cstr
+    ptr.add(s.len()).write(0);
+
+    IBytes(NonNull::new_unchecked(ptr))
 }
 
 impl IBytes {
@@ -249,6 +591,8 @@ impl IBytes {
         static EMPTY_BYTES: InternedStringData<1> = InternedStringData {
             hash: crate::hasher::EMPTY_HASH,
             len: 0,
+            #[cfg(feature = "symbol")]
+            symbol: AtomicU32::new(0),
             #[cfg(feature = "cache-utf8")]
             is_valid_utf8: true,
             data: [0],
@@ -259,8 +603,31 @@ impl IBytes {
         IBytes(unsafe { NonNull::new_unchecked(x.cast_mut()) })
     }
 
+    /// # Safety
+    ///
+    /// `ptr` must have come from `IBytes::as_ptr`
+    #[inline]
+    pub(crate) unsafe fn from_ptr(ptr: NonNull<u8>) -> Self {
+        IBytes(ptr)
+    }
+
     #[inline]
-    fn header_ptr(self) -> *mut InternedStringHeader {
+    pub(crate) fn as_ptr(self) -> NonNull<u8> {
+        self.0
+    }
+
+    /// # Safety
+    ///
+    /// The string must not be accessed concurrently, and if its hash doesn't match `hash`
+    /// it must have come from `StaticIStrData`
+    pub(crate) unsafe fn init_hash(self, hash: u64) {
+        if (*self.header_ptr()).hash != hash {
+            (*self.header_ptr()).hash = hash;
+        }
+    }
+
+    #[inline]
+    pub(crate) fn header_ptr(self) -> *mut InternedStringHeader {
         let offset = unsafe {
             let data = MaybeUninit::<InternedStringHeader>::uninit();
             let end = core::ptr::addr_of!((*data.as_ptr()).data).cast::<u8>();
@@ -289,11 +656,16 @@ impl IBytes {
     #[inline]
     pub fn saved_hash(self) -> u64 {
         let ptr = self.header_ptr();
-        unsafe { (*ptr).hash }
+        let hash = unsafe { (*ptr).hash };
+        debug_assert!(
+            hash != UNREGISTERED_HASH || hash == crate::hasher::hash(self.to_bytes()),
+            "a static_istr! string was used before it was passed to register_static"
+        );
+        hash
     }
 
     #[inline]
-    pub fn as_cstr_ptr(self) -> *const std::ffi::c_char {
+    pub fn as_cstr_ptr(self) -> *const core::ffi::c_char {
         self.0.as_ptr().cast()
     }
 
@@ -306,6 +678,12 @@ impl IBytes {
     pub fn is_valid_utf8(self) -> bool {
         unsafe { (*self.header_ptr()).is_valid_utf8 }
     }
+
+    // the symbol of this string plus one, or zero if it hasn't been assigned one yet
+    #[cfg(feature = "symbol")]
+    pub(crate) fn symbol_slot(self) -> &'static AtomicU32 {
+        unsafe { &(*self.header_ptr()).symbol }
+    }
 }
 
 impl Default for IBytes {
@@ -366,7 +744,7 @@ impl IStr {
     ///
     /// The bytes must represent valid utf-8
     #[inline]
-    pub unsafe fn from_utf8_unchecked(bytes: IBytes) -> Self {
+    pub const unsafe fn from_utf8_unchecked(bytes: IBytes) -> Self {
         Self(bytes)
     }
 
@@ -401,7 +779,7 @@ impl IStr {
     }
 
     #[inline]
-    pub fn as_cstr_ptr(self) -> *const std::ffi::c_char {
+    pub fn as_cstr_ptr(self) -> *const core::ffi::c_char {
         self.0.as_cstr_ptr()
     }
 
@@ -448,31 +826,31 @@ impl Deref for IStr {
 }
 
 impl core::fmt::Debug for IBytes {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         self.to_bytes().fmt(f)
     }
 }
 
 impl core::fmt::Pointer for IBytes {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         self.0.fmt(f)
     }
 }
 
 impl core::fmt::Debug for IStr {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         self.to_str().fmt(f)
     }
 }
 
 impl core::fmt::Display for IStr {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         self.to_str().fmt(f)
     }
 }
 
 impl core::fmt::Pointer for IStr {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         self.0.fmt(f)
     }
 }
@@ -494,3 +872,83 @@ fn test_large_string() {
     let large = include_str!("../../fixtures/large_string.txt");
     assert_eq!(new(large).to_str(), new(large).to_str())
 }
+
+#[cfg(all(feature = "std", not(feature = "single-threaded")))]
+#[test]
+fn test_recycle() {
+    let mut recycled = Vec::new();
+
+    let mut first = Arena::new_recycling();
+    let a = first.alloc(64);
+    let chunk = first.chunk;
+    first.retire(&mut recycled);
+    assert_eq!(recycled.len(), 1);
+
+    let mut second = Arena::new_recycling();
+    assert_eq!(second.adopt(&mut recycled), Some(chunk));
+    let b = second.alloc(64);
+    assert_eq!(b, unsafe { a.sub(64) });
+    assert_eq!(second.stats.chunks, 1);
+    assert!(recycled.is_empty());
+}
+
+#[test]
+fn test_config() {
+    let config = ArenaConfig::new()
+        .initial_size(1024)
+        .growth_factor(1)
+        .large_string_cutoff(512);
+
+    let mut arena = Arena::new();
+    arena.alloc_with(64, &config);
+    assert_eq!(arena.stats.chunks, 1);
+    assert_eq!(arena.stats.reserved_bytes, 1024);
+
+    for _ in 0..64 {
+        arena.alloc_with(256, &config);
+    }
+
+    assert_eq!(arena.stats.reserved_bytes, arena.stats.chunks * 1024);
+
+    arena.alloc_with(513, &config);
+    assert_eq!(arena.stats.large_allocs, 1);
+
+    // a string that doesn't fit in the next chunk must get a dedicated allocation
+    arena.alloc_with(
+        1024,
+        &ArenaConfig::new().initial_size(1024).growth_factor(1),
+    );
+    assert_eq!(arena.stats.large_allocs, 2);
+
+    unsafe { arena.dealloc_all() }
+}
+
+#[test]
+fn test_chunk_allocator() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Counting(AtomicUsize);
+
+    unsafe impl ChunkAllocator for Counting {
+        fn alloc(&self, layout: Layout) -> *mut u8 {
+            self.0.fetch_add(layout.size(), Ordering::Relaxed);
+            unsafe { std::alloc::alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            self.0.fetch_sub(layout.size(), Ordering::Relaxed);
+            std::alloc::dealloc(ptr, layout)
+        }
+    }
+
+    static COUNTING: Counting = Counting(AtomicUsize::new(0));
+
+    let config = ArenaConfig::new().initial_size(1024).allocator(&COUNTING);
+    let mut arena = Arena::new();
+    arena.alloc_with(64, &config);
+    arena.alloc_with(4096, &config);
+    assert_eq!(COUNTING.0.load(Ordering::Relaxed), 1024 + 4096);
+
+    unsafe { arena.dealloc_all() }
+    assert_eq!(COUNTING.0.load(Ordering::Relaxed), 0);
+}
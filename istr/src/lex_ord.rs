@@ -0,0 +1,127 @@
+use alloc::{collections::BTreeMap, collections::BTreeSet, vec::Vec};
+use core::{cmp::Ordering, ops::Deref};
+
+use crate::{IBytes, IStr};
+
+// Orders interned strings by their contents instead of by address, so the order is the same on
+// every run. Equal strings are still found with a single pointer comparison.
+//
+// With the `lex-ord` feature `IStr` and `IBytes` are ordered this way directly.
+#[repr(transparent)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LexOrd<T>(pub T);
+
+pub type IStrBTreeMap<V> = BTreeMap<LexOrd<IStr>, V>;
+pub type IStrBTreeSet = BTreeSet<LexOrd<IStr>>;
+pub type IBytesBTreeMap<V> = BTreeMap<LexOrd<IBytes>, V>;
+pub type IBytesBTreeSet = BTreeSet<LexOrd<IBytes>>;
+
+#[inline]
+pub(crate) fn cmp_bytes(a: IBytes, b: IBytes) -> Ordering {
+    if a == b {
+        Ordering::Equal
+    } else {
+        a.to_bytes().cmp(b.to_bytes())
+    }
+}
+
+impl Ord for LexOrd<IBytes> {
+    #[inline]
+    fn cmp(&self, other: &Self) -> Ordering {
+        cmp_bytes(self.0, other.0)
+    }
+}
+
+impl PartialOrd for LexOrd<IBytes> {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// `str`s are ordered by their bytes
+impl Ord for LexOrd<IStr> {
+    #[inline]
+    fn cmp(&self, other: &Self) -> Ordering {
+        cmp_bytes(self.0.to_ibytes(), other.0.to_ibytes())
+    }
+}
+
+impl PartialOrd for LexOrd<IStr> {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Deref for LexOrd<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> From<T> for LexOrd<T> {
+    fn from(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl<T: core::fmt::Display> core::fmt::Display for LexOrd<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+// The entries of a map keyed by interned strings, such as an `IStrMap`, sorted by key
+pub fn sorted_entries<'a, K, V>(map: impl IntoIterator<Item = (&'a K, &'a V)>) -> Vec<(K, &'a V)>
+where
+    K: Copy + 'a,
+    LexOrd<K>: Ord,
+{
+    let mut entries = map
+        .into_iter()
+        .map(|(&key, value)| (key, value))
+        .collect::<Vec<_>>();
+    entries.sort_unstable_by_key(|&(key, _)| LexOrd(key));
+    entries
+}
+
+#[test]
+fn test_lex_ord() {
+    let mut strings = ["lex b", "lex a", "lex c", "lex a"].map(|s| LexOrd(IStr::new(s)));
+    strings.sort();
+
+    assert_eq!(
+        strings.map(|s| s.to_str()),
+        ["lex a", "lex a", "lex b", "lex c"]
+    );
+    assert!(LexOrd(IBytes::new(b"lex")) < LexOrd(IBytes::new(b"lex\xff")));
+
+    if cfg!(feature = "lex-ord") {
+        assert!(IStr::new("lex b") > IStr::new("lex a"));
+    }
+
+    let map = IStrBTreeMap::from([
+        (LexOrd(IStr::new("lex z")), 1),
+        (LexOrd(IStr::new("lex y")), 2),
+    ]);
+    assert_eq!(map.keys().next().map(|key| key.to_str()), Some("lex y"));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_sorted_entries() {
+    let mut map = crate::IStrMap::default();
+
+    for i in (0..64).rev() {
+        map.insert(IStr::new(&format!("sorted {i:02}")), i);
+    }
+
+    let entries = sorted_entries(&map);
+    assert!(entries
+        .iter()
+        .enumerate()
+        .all(|(i, &(_, &value))| value == i));
+}
@@ -1,49 +1,83 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
 pub use leaky_alloc::{IBytes, IStr};
 
 impl nohash_hasher::IsEnabled for IStr {}
 pub type IStrHasher = nohash_hasher::NoHashHasher<IStr>;
 pub type IStrBuildHasher = nohash_hasher::BuildNoHashHasher<IStr>;
+#[cfg(feature = "std")]
 pub type IStrMap<V> = std::collections::HashMap<IStr, V, IStrBuildHasher>;
+#[cfg(feature = "std")]
 pub type IStrSet = std::collections::HashSet<IStr, IStrBuildHasher>;
 
 impl nohash_hasher::IsEnabled for IBytes {}
 pub type IBytesHasher = nohash_hasher::NoHashHasher<IBytes>;
 pub type IBytesBuildHasher = nohash_hasher::BuildNoHashHasher<IBytes>;
+#[cfg(feature = "std")]
 pub type IBytesMap<V> = std::collections::HashMap<IBytes, V, IBytesBuildHasher>;
+#[cfg(feature = "std")]
 pub type IBytesSet = std::collections::HashSet<IBytes, IBytesBuildHasher>;
 
 mod hasher;
 mod leaky_alloc;
 
+mod arc;
+mod builder;
 mod cache;
-
-pub use cache::{clear_local_cache, len, local_cache_size};
+mod config;
+pub mod interner;
+mod lazy;
+mod lex_ord;
+pub mod map;
+pub mod normalize;
+#[cfg(feature = "serde")]
+mod serde;
+#[cfg(feature = "std")]
+pub mod snapshot;
+mod static_istr;
+mod stats;
+#[cfg(feature = "symbol")]
+mod symbol;
+mod sync;
+mod table;
+
+pub use arc::{arc_len, ArcIStr};
+pub use builder::IStrBuilder;
+#[cfg(feature = "std")]
+pub use cache::{
+    clear_local_cache, local_cache_capacity, local_cache_size, set_local_cache_capacity,
+};
+pub use cache::{istr_items, items, items_snapshot, items_sorted, len};
+pub use config::{ArenaConfig, ChunkAllocator, StdAllocator};
+pub use interner::Interner;
+pub use lazy::LazyIStr;
+pub use lex_ord::{
+    sorted_entries, IBytesBTreeMap, IBytesBTreeSet, IStrBTreeMap, IStrBTreeSet, LexOrd,
+};
+pub use static_istr::register_static;
+#[cfg(feature = "std")]
+pub use stats::thread_memory_stats;
+pub use stats::{local_memory_stats, memory_stats, MemoryStats};
+#[cfg(feature = "symbol")]
+pub use symbol::Symbol;
 
 #[cfg(ISTR_GLOBAL_CACHE_CLEAR)]
 pub use cache::clear_global_cache;
 
-pub fn items() -> impl Iterator<Item = IBytes> {
-    use hashbrown::raw;
-
-    pub struct TableIterator {
-        _guard: std::sync::MutexGuard<'static, raw::RawTable<IBytes>>,
-        iter: raw::RawIter<IBytes>,
-    }
-
-    impl Iterator for TableIterator {
-        type Item = IBytes;
-
-        fn next(&mut self) -> Option<Self::Item> {
-            self.iter.next().map(|bucket| unsafe { *bucket.as_ref() })
-        }
-    }
+// The hash used by the interner, this is always equal to the `saved_hash` of the string
+// interned from `s`, and can be passed to `new_with_hash` and `get_with_hash`.
+#[inline]
+pub fn hash(s: &[u8]) -> u64 {
+    hasher::hash(s)
+}
 
-    cache::tables().flat_map(|table| unsafe {
-        TableIterator {
-            iter: table.iter(),
-            _guard: table,
-        }
-    })
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::{builder::format, leaky_alloc::StaticIStrData};
 }
 
 impl IBytes {
@@ -62,6 +96,31 @@ impl IBytes {
         cache::get(s)
     }
 
+    /// # Safety
+    ///
+    /// `hash` must be equal to `istr::hash(s)`. Otherwise the string may be interned a second
+    /// time under the wrong hash, and the returned handle won't be equal to the one returned by
+    /// `new`. This is only checked with debug assertions.
+    #[inline]
+    pub unsafe fn new_with_hash(s: &[u8], hash: u64) -> Self {
+        debug_assert_eq!(hash, hasher::hash(s));
+        cache::new_imp_local(s, hash)
+    }
+
+    // a wrong hash can't create a duplicate, but the lookup will likely miss
+    #[inline]
+    pub fn get_with_hash(s: &[u8], hash: u64) -> Option<Self> {
+        cache::get_imp_local(s, hash)
+    }
+
+    pub fn new_many(strings: &[&[u8]]) -> Vec<Self> {
+        cache::new_many(strings)
+    }
+
+    pub fn get_many(strings: &[&[u8]]) -> Vec<Option<Self>> {
+        cache::get_many(strings)
+    }
+
     #[inline]
     pub fn get_skip_local(s: &[u8]) -> Option<Self> {
         cache::get_skip_local(s)
@@ -84,8 +143,40 @@ impl IStr {
         Some(unsafe { IStr::from_utf8_unchecked(IBytes::get(s.as_bytes())?) })
     }
 
+    /// # Safety
+    ///
+    /// `hash` must be equal to `istr::hash(s.as_bytes())`, see `IBytes::new_with_hash`
+    #[inline]
+    pub unsafe fn new_with_hash(s: &str, hash: u64) -> Self {
+        unsafe { IStr::from_utf8_unchecked(IBytes::new_with_hash(s.as_bytes(), hash)) }
+    }
+
+    #[inline]
+    pub fn get_with_hash(s: &str, hash: u64) -> Option<Self> {
+        Some(unsafe { IStr::from_utf8_unchecked(IBytes::get_with_hash(s.as_bytes(), hash)?) })
+    }
+
+    pub fn new_many(strings: &[&str]) -> Vec<Self> {
+        cache::new_many(strings)
+            .into_iter()
+            .map(|ibytes| unsafe { IStr::from_utf8_unchecked(ibytes) })
+            .collect()
+    }
+
+    pub fn get_many(strings: &[&str]) -> Vec<Option<Self>> {
+        cache::get_many(strings)
+            .into_iter()
+            .map(|ibytes| Some(unsafe { IStr::from_utf8_unchecked(ibytes?) }))
+            .collect()
+    }
+
     #[inline]
     pub fn get_skip_local(s: &str) -> Option<Self> {
         Some(unsafe { IStr::from_utf8_unchecked(IBytes::get_skip_local(s.as_bytes())?) })
     }
+
+    // interns the strings joined together, without allocating a `String` for them
+    pub fn concat(strings: &[IStr]) -> Self {
+        builder::concat(strings)
+    }
 }
@@ -0,0 +1,294 @@
+use core::{
+    hash::{Hash, Hasher},
+    ops::{Deref, DerefMut},
+};
+
+use hashbrown::{Equivalent, HashMap, HashSet};
+
+use crate::{IStr, IStrBuildHasher};
+
+// A map and set keyed by `IStr` that can be queried with a `&str` without interning it, so a
+// lookup for a string that was never interned doesn't allocate. Everything else is the same as
+// hashbrown's `HashMap` and `HashSet`, which they deref to.
+//
+// The keys are hashed with their saved hash like the `IStrMap` alias at the crate root, the query
+// string is hashed with the interner's hash function, then compared by contents.
+pub struct StrLookupMap<V>(HashMap<IStr, V, IStrBuildHasher>);
+
+pub struct StrLookupSet(HashSet<IStr, IStrBuildHasher>);
+
+struct StrKey<'a> {
+    s: &'a str,
+    hash: u64,
+}
+
+impl<'a> StrKey<'a> {
+    fn new(s: &'a str) -> Self {
+        Self {
+            s,
+            hash: crate::hash(s.as_bytes()),
+        }
+    }
+}
+
+// must match the `Hash` impl of `IStr`
+impl Hash for StrKey<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.hash.hash(state)
+    }
+}
+
+impl Equivalent<IStr> for StrKey<'_> {
+    fn equivalent(&self, key: &IStr) -> bool {
+        key.saved_hash() == self.hash && key.to_str() == self.s
+    }
+}
+
+impl<V> StrLookupMap<V> {
+    pub fn new() -> Self {
+        Self(HashMap::default())
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self(HashMap::with_capacity_and_hasher(
+            capacity,
+            Default::default(),
+        ))
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<&V> {
+        self.0.get(&StrKey::new(key))
+    }
+
+    pub fn get_str_mut(&mut self, key: &str) -> Option<&mut V> {
+        self.0.get_mut(&StrKey::new(key))
+    }
+
+    pub fn get_key_value_str(&self, key: &str) -> Option<(&IStr, &V)> {
+        self.0.get_key_value(&StrKey::new(key))
+    }
+
+    pub fn contains_str(&self, key: &str) -> bool {
+        self.0.contains_key(&StrKey::new(key))
+    }
+
+    pub fn remove_str(&mut self, key: &str) -> Option<V> {
+        self.0.remove(&StrKey::new(key))
+    }
+
+    pub fn into_inner(self) -> HashMap<IStr, V, IStrBuildHasher> {
+        self.0
+    }
+}
+
+impl StrLookupSet {
+    pub fn new() -> Self {
+        Self(HashSet::default())
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self(HashSet::with_capacity_and_hasher(
+            capacity,
+            Default::default(),
+        ))
+    }
+
+    // the interned string equal to `value`, if it's in the set
+    pub fn get_str(&self, value: &str) -> Option<IStr> {
+        self.0.get(&StrKey::new(value)).copied()
+    }
+
+    pub fn contains_str(&self, value: &str) -> bool {
+        self.0.contains(&StrKey::new(value))
+    }
+
+    pub fn remove_str(&mut self, value: &str) -> bool {
+        self.0.remove(&StrKey::new(value))
+    }
+
+    pub fn into_inner(self) -> HashSet<IStr, IStrBuildHasher> {
+        self.0
+    }
+}
+
+impl<V> Default for StrLookupMap<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Default for StrLookupSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> Deref for StrLookupMap<V> {
+    type Target = HashMap<IStr, V, IStrBuildHasher>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<V> DerefMut for StrLookupMap<V> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl Deref for StrLookupSet {
+    type Target = HashSet<IStr, IStrBuildHasher>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for StrLookupSet {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<V: Clone> Clone for StrLookupMap<V> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl Clone for StrLookupSet {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<V: core::fmt::Debug> core::fmt::Debug for StrLookupMap<V> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl core::fmt::Debug for StrLookupSet {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<V: PartialEq> PartialEq for StrLookupMap<V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<V: Eq> Eq for StrLookupMap<V> {}
+
+impl PartialEq for StrLookupSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl Eq for StrLookupSet {}
+
+impl<V> FromIterator<(IStr, V)> for StrLookupMap<V> {
+    fn from_iter<I: IntoIterator<Item = (IStr, V)>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl FromIterator<IStr> for StrLookupSet {
+    fn from_iter<I: IntoIterator<Item = IStr>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl<V> Extend<(IStr, V)> for StrLookupMap<V> {
+    fn extend<I: IntoIterator<Item = (IStr, V)>>(&mut self, iter: I) {
+        self.0.extend(iter)
+    }
+}
+
+impl Extend<IStr> for StrLookupSet {
+    fn extend<I: IntoIterator<Item = IStr>>(&mut self, iter: I) {
+        self.0.extend(iter)
+    }
+}
+
+impl<V> IntoIterator for StrLookupMap<V> {
+    type Item = (IStr, V);
+    type IntoIter = hashbrown::hash_map::IntoIter<IStr, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a, V> IntoIterator for &'a StrLookupMap<V> {
+    type Item = (&'a IStr, &'a V);
+    type IntoIter = hashbrown::hash_map::Iter<'a, IStr, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl<'a, V> IntoIterator for &'a mut StrLookupMap<V> {
+    type Item = (&'a IStr, &'a mut V);
+    type IntoIter = hashbrown::hash_map::IterMut<'a, IStr, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter_mut()
+    }
+}
+
+impl IntoIterator for StrLookupSet {
+    type Item = IStr;
+    type IntoIter = hashbrown::hash_set::IntoIter<IStr>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a StrLookupSet {
+    type Item = &'a IStr;
+    type IntoIter = hashbrown::hash_set::Iter<'a, IStr>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+#[test]
+fn test_map() {
+    let mut map = StrLookupMap::new();
+    map.insert(IStr::new("map key"), 1);
+    map.insert(IStr::new(""), 2);
+
+    assert_eq!(map.get_str("map key"), Some(&1));
+    assert_eq!(map.get_str(""), Some(&2));
+    *map.get_str_mut("map key").unwrap() += 10;
+    assert_eq!(map[&IStr::new("map key")], 11);
+
+    // a miss must not intern the query
+    assert_eq!(map.get_str("map never interned"), None);
+    assert!(!map.contains_str("map never interned"));
+    assert_eq!(IStr::get("map never interned"), None);
+
+    assert_eq!(map.remove_str("map key"), Some(11));
+    assert!(!map.contains_str("map key"));
+}
+
+#[test]
+fn test_set() {
+    let set = ["set a", "set b"]
+        .map(IStr::new)
+        .into_iter()
+        .collect::<StrLookupSet>();
+
+    assert_eq!(set.get_str("set a"), Some(IStr::new("set a")));
+    assert!(set.contains_str("set b"));
+    assert_eq!(set.get_str("set never interned"), None);
+    assert_eq!(IStr::get("set never interned"), None);
+}
@@ -0,0 +1,186 @@
+use alloc::string::String;
+
+use crate::{cache, hasher, table::ShardedTable, IStr};
+
+// Maps strings to a normal form before they are interned, so that all strings with the
+// same normal form share a single handle
+pub trait Normalizer {
+    // if this returns true, `s` is interned as is without copying it
+    fn is_normalized(&self, s: &str) -> bool;
+
+    // appends the normal form of `s` to `out`
+    fn normalize_into(&self, s: &str, out: &mut String);
+}
+
+// Each normalizer gets its own table of the strings that were interned through it. The normal
+// forms themselves are interned in the global cache, so a handle from a `NormalizedInterner` is
+// the same handle `IStr::new` returns for the normal form, and equal handles always have equal
+// contents.
+pub struct NormalizedInterner<N> {
+    normalizer: N,
+    table: ShardedTable,
+}
+
+pub static ASCII_CASE_INSENSITIVE: NormalizedInterner<AsciiCaseFold> =
+    NormalizedInterner::new(AsciiCaseFold);
+
+#[cfg(feature = "unicode")]
+pub static NFC: NormalizedInterner<Nfc> = NormalizedInterner::new(Nfc);
+
+#[cfg(feature = "unicode")]
+pub static NFKC: NormalizedInterner<Nfkc> = NormalizedInterner::new(Nfkc);
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AsciiCaseFold;
+
+impl Normalizer for AsciiCaseFold {
+    #[inline]
+    fn is_normalized(&self, s: &str) -> bool {
+        !s.bytes().any(|b| b.is_ascii_uppercase())
+    }
+
+    fn normalize_into(&self, s: &str, out: &mut String) {
+        let start = out.len();
+        out.push_str(s);
+        out[start..].make_ascii_lowercase();
+    }
+}
+
+#[cfg(feature = "unicode")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Nfc;
+
+#[cfg(feature = "unicode")]
+impl Normalizer for Nfc {
+    fn is_normalized(&self, s: &str) -> bool {
+        unicode_normalization::is_nfc_quick(s.chars()) == unicode_normalization::IsNormalized::Yes
+    }
+
+    fn normalize_into(&self, s: &str, out: &mut String) {
+        out.extend(unicode_normalization::UnicodeNormalization::nfc(s))
+    }
+}
+
+#[cfg(feature = "unicode")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Nfkc;
+
+#[cfg(feature = "unicode")]
+impl Normalizer for Nfkc {
+    fn is_normalized(&self, s: &str) -> bool {
+        unicode_normalization::is_nfkc_quick(s.chars()) == unicode_normalization::IsNormalized::Yes
+    }
+
+    fn normalize_into(&self, s: &str, out: &mut String) {
+        out.extend(unicode_normalization::UnicodeNormalization::nfkc(s))
+    }
+}
+
+#[cfg(feature = "std")]
+fn with_normalized<N: Normalizer, O>(normalizer: &N, s: &str, f: impl FnOnce(&str) -> O) -> O {
+    if normalizer.is_normalized(s) {
+        return f(s);
+    }
+
+    crate::builder::with_scratch(|buffer| {
+        // the scratch buffer is empty, so this can't fail
+        let mut normalized = String::from_utf8(core::mem::take(buffer)).unwrap();
+        normalizer.normalize_into(s, &mut normalized);
+        let output = f(&normalized);
+        *buffer = normalized.into_bytes();
+        output
+    })
+}
+
+#[cfg(not(feature = "std"))]
+fn with_normalized<N: Normalizer, O>(normalizer: &N, s: &str, f: impl FnOnce(&str) -> O) -> O {
+    if normalizer.is_normalized(s) {
+        return f(s);
+    }
+
+    let mut buffer = String::new();
+    normalizer.normalize_into(s, &mut buffer);
+    f(&buffer)
+}
+
+impl<N: Normalizer> NormalizedInterner<N> {
+    pub const fn new(normalizer: N) -> Self {
+        Self {
+            normalizer,
+            table: ShardedTable::new(),
+        }
+    }
+
+    pub fn normalizer(&self) -> &N {
+        &self.normalizer
+    }
+
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn intern(&self, s: &str) -> IStr {
+        with_normalized(&self.normalizer, s, |s| {
+            let s = s.as_bytes();
+            let hash = hasher::hash(s);
+            let ibytes = self
+                .table
+                .get_or_insert_with(s, hash, || cache::new_imp_local(s, hash));
+            unsafe { IStr::from_utf8_unchecked(ibytes) }
+        })
+    }
+
+    pub fn get(&self, s: &str) -> Option<IStr> {
+        with_normalized(&self.normalizer, s, |s| {
+            let s = s.as_bytes();
+            let ibytes = self.table.get(s, hasher::hash(s))?;
+            Some(unsafe { IStr::from_utf8_unchecked(ibytes) })
+        })
+    }
+}
+
+#[test]
+fn test_ascii() {
+    let a = ASCII_CASE_INSENSITIVE.intern("Content-Type");
+    let b = ASCII_CASE_INSENSITIVE.intern("content-type");
+    assert_eq!(a, b);
+    assert_eq!(a.to_str(), "content-type");
+    assert_eq!(ASCII_CASE_INSENSITIVE.get("CONTENT-TYPE"), Some(a));
+    assert_eq!(a, IStr::new("content-type"));
+    assert_eq!(ASCII_CASE_INSENSITIVE.get("Accept"), None);
+}
+
+#[test]
+fn test_custom() {
+    struct Trim;
+
+    impl Normalizer for Trim {
+        fn is_normalized(&self, s: &str) -> bool {
+            s.trim() == s
+        }
+
+        fn normalize_into(&self, s: &str, out: &mut String) {
+            out.push_str(s.trim())
+        }
+    }
+
+    static TRIM: NormalizedInterner<Trim> = NormalizedInterner::new(Trim);
+
+    assert_eq!(TRIM.intern("  trimmed "), TRIM.intern("trimmed"));
+    assert_eq!(TRIM.get("missing "), None);
+}
+
+#[cfg(feature = "unicode")]
+#[test]
+fn test_unicode() {
+    // U+212B ANGSTROM SIGN, U+00C5 and A + U+030A COMBINING RING ABOVE are canonically equivalent
+    assert_eq!(NFC.intern("\u{212b}"), NFC.intern("\u{c5}"));
+    assert_eq!(NFC.intern("A\u{30a}"), NFC.intern("\u{c5}"));
+    // U+FB01 LATIN SMALL LIGATURE FI is only compatibility equivalent to "fi"
+    assert_ne!(NFC.intern("\u{fb01}"), NFC.intern("fi"));
+    assert_eq!(NFKC.intern("\u{fb01}"), NFKC.intern("fi"));
+}
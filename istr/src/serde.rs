@@ -0,0 +1,204 @@
+use serde::{
+    de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor},
+    ser::{Serialize, Serializer},
+};
+
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use crate::{
+    map::{StrLookupMap, StrLookupSet},
+    IBytes, IStr,
+};
+
+impl Serialize for IStr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.to_str())
+    }
+}
+
+impl Serialize for IBytes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.to_bytes())
+    }
+}
+
+struct IStrVisitor;
+
+impl<'de> Visitor<'de> for IStrVisitor {
+    type Value = IStr;
+
+    fn expecting(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.write_str("a string")
+    }
+
+    // borrowed and owned strings are forwarded here, so they are interned without
+    // allocating a temporary `String` first
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(IStr::new(v))
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        match core::str::from_utf8(v) {
+            Ok(s) => Ok(IStr::new(s)),
+            Err(_) => Err(E::invalid_value(de::Unexpected::Bytes(v), &self)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for IStr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(IStrVisitor)
+    }
+}
+
+struct IBytesVisitor;
+
+impl<'de> Visitor<'de> for IBytesVisitor {
+    type Value = IBytes;
+
+    fn expecting(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.write_str("a byte array")
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        Ok(IBytes::new(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(IBytes::new(v.as_bytes()))
+    }
+
+    // formats without native byte strings (like json) encode them as a sequence of integers
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+
+        Ok(IBytes::new(&bytes))
+    }
+}
+
+impl<'de> Deserialize<'de> for IBytes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_bytes(IBytesVisitor)
+    }
+}
+
+impl<V: Serialize> Serialize for StrLookupMap<V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+impl Serialize for StrLookupSet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+struct StrLookupMapVisitor<V>(PhantomData<V>);
+
+impl<'de, V: Deserialize<'de>> Visitor<'de> for StrLookupMapVisitor<V> {
+    type Value = StrLookupMap<V>;
+
+    fn expecting(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.write_str("a map with string keys")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+        let mut map = StrLookupMap::with_capacity(access.size_hint().unwrap_or(0).min(4096));
+
+        while let Some((key, value)) = access.next_entry::<IStr, V>()? {
+            map.insert(key, value);
+        }
+
+        Ok(map)
+    }
+}
+
+impl<'de, V: Deserialize<'de>> Deserialize<'de> for StrLookupMap<V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(StrLookupMapVisitor(PhantomData))
+    }
+}
+
+struct StrLookupSetVisitor;
+
+impl<'de> Visitor<'de> for StrLookupSetVisitor {
+    type Value = StrLookupSet;
+
+    fn expecting(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.write_str("a sequence of strings")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut set = StrLookupSet::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+
+        while let Some(value) = seq.next_element::<IStr>()? {
+            set.insert(value);
+        }
+
+        Ok(set)
+    }
+}
+
+impl<'de> Deserialize<'de> for StrLookupSet {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_seq(StrLookupSetVisitor)
+    }
+}
+
+#[test]
+fn test_istr() {
+    let s = IStr::new("serde hello");
+    let json = serde_json::to_string(&s).unwrap();
+    assert_eq!(json, r#""serde hello""#);
+    assert_eq!(serde_json::from_str::<IStr>(&json).unwrap(), s);
+    // escaped strings can't be borrowed from the input
+    assert_eq!(
+        serde_json::from_str::<IStr>(r#""serde\u0020hello""#).unwrap(),
+        s
+    );
+}
+
+#[test]
+fn test_ibytes() {
+    let b = IBytes::new(b"serde \xff bytes");
+    let json = serde_json::to_string(&b).unwrap();
+    assert_eq!(serde_json::from_str::<IBytes>(&json).unwrap(), b);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_collections() {
+    let mut map = crate::IStrMap::default();
+    map.insert(IStr::new("serde key"), 1);
+    map.insert(IStr::new("serde other key"), 2);
+    let json = serde_json::to_string(&map).unwrap();
+    assert_eq!(
+        serde_json::from_str::<crate::IStrMap<i32>>(&json).unwrap(),
+        map
+    );
+
+    let set = map.keys().copied().collect::<crate::IStrSet>();
+    let json = serde_json::to_string(&set).unwrap();
+    assert_eq!(serde_json::from_str::<crate::IStrSet>(&json).unwrap(), set);
+}
+
+#[test]
+fn test_lookup_collections() {
+    let mut map = StrLookupMap::new();
+    map.insert(IStr::new("serde lookup key"), 1);
+    map.insert(IStr::new("serde other lookup key"), 2);
+    let json = serde_json::to_string(&map).unwrap();
+    let decoded = serde_json::from_str::<StrLookupMap<i32>>(&json).unwrap();
+    assert_eq!(decoded, map);
+    assert_eq!(decoded.get_str("serde lookup key"), Some(&1));
+
+    let set = map.keys().copied().collect::<StrLookupSet>();
+    let json = serde_json::to_string(&set).unwrap();
+    assert_eq!(serde_json::from_str::<StrLookupSet>(&json).unwrap(), set);
+}
@@ -0,0 +1,189 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{cache, hasher};
+
+// Snapshot layout, all integers are little endian
+//
+// magic: [u8; 8]
+// version: u32
+// reserved: u32
+// fingerprint: u64
+// count: u64
+// entries: [{ hash: u64, len: u64, bytes: [u8; len] }; count]
+
+const MAGIC: [u8; 8] = *b"ISTRSNAP";
+const VERSION: u32 = 1;
+
+// The saved hashes are only valid if they were produced by the same hash function, so the
+// hash of a fixed probe string is stored alongside them to detect a mismatch. It's hashed without
+// the `random-seed` seeds, so that snapshots can be loaded by other processes.
+fn fingerprint() -> u64 {
+    hasher::unseeded_hash(b"istr snapshot fingerprint")
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub fn save(path: impl AsRef<Path>) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write(&mut writer)?;
+    writer.flush()
+}
+
+// Snapshots must be loaded after every `register_static` call. Loading one first interns the
+// strings that the statics were going to provide, so registering them afterwards fails with a
+// conflict and the statics are never equal to the strings returned by `IStr::new`.
+//
+// With the `mmap` feature the file is memory mapped instead of read into a buffer, it must not
+// be modified while it's loading. Either way every string is copied once into the arena, since
+// each one needs a header in front of it.
+#[cfg(feature = "mmap")]
+pub fn load(path: impl AsRef<Path>) -> io::Result<usize> {
+    let file = File::open(path)?;
+    let data = unsafe { memmap2::Mmap::map(&file)? };
+    read_slice(&data)
+}
+
+#[cfg(not(feature = "mmap"))]
+pub fn load(path: impl AsRef<Path>) -> io::Result<usize> {
+    read(io::BufReader::new(File::open(path)?))
+}
+
+pub fn write(mut writer: impl Write) -> io::Result<()> {
+    // the count is written before the entries, so it has to match even if strings are interned
+    // while writing
+    let items = crate::items_snapshot().collect::<Vec<_>>();
+
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&0u32.to_le_bytes())?;
+    writer.write_all(&fingerprint().to_le_bytes())?;
+    writer.write_all(&(items.len() as u64).to_le_bytes())?;
+
+    for ibytes in items {
+        writer.write_all(&ibytes.saved_hash().to_le_bytes())?;
+        writer.write_all(&(ibytes.len() as u64).to_le_bytes())?;
+        writer.write_all(ibytes.to_bytes())?;
+    }
+
+    Ok(())
+}
+
+// returns the number of strings in the snapshot, strings that were already interned keep their
+// existing handles. Like `load`, this must run after every `register_static` call.
+pub fn read(mut reader: impl Read) -> io::Result<usize> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    read_slice(&data)
+}
+
+// Nothing is interned unless the whole snapshot is valid. Every string is rehashed, with
+// `random-seed` the saved hashes were made with another process's seeds so they are ignored,
+// otherwise a saved hash that doesn't match means the snapshot is corrupt.
+fn read_slice(mut data: &[u8]) -> io::Result<usize> {
+    fn take<'a>(data: &mut &'a [u8], len: usize) -> io::Result<&'a [u8]> {
+        if data.len() < len {
+            return Err(invalid_data("truncated istr snapshot"));
+        }
+
+        let (bytes, rest) = data.split_at(len);
+        *data = rest;
+        Ok(bytes)
+    }
+
+    fn take_u64(data: &mut &[u8]) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(take(data, 8)?.try_into().unwrap()))
+    }
+
+    fn take_u32(data: &mut &[u8]) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(take(data, 4)?.try_into().unwrap()))
+    }
+
+    if take(&mut data, MAGIC.len())? != MAGIC {
+        return Err(invalid_data("not an istr snapshot"));
+    }
+
+    if take_u32(&mut data)? != VERSION {
+        return Err(invalid_data("unsupported istr snapshot version"));
+    }
+
+    take_u32(&mut data)?;
+
+    if take_u64(&mut data)? != fingerprint() {
+        return Err(invalid_data(
+            "istr snapshot was created with a different hash function",
+        ));
+    }
+
+    let count = take_u64(&mut data)?;
+    // every entry takes at least 16 bytes, so a corrupt count can't cause a huge allocation
+    let mut entries = Vec::with_capacity(count.min(data.len() as u64 / 16) as usize);
+
+    for _ in 0..count {
+        let saved_hash = take_u64(&mut data)?;
+        let len = take_u64(&mut data)?;
+        let len = usize::try_from(len).map_err(|_| invalid_data("string too large"))?;
+        let bytes = take(&mut data, len)?;
+        let hash = hasher::hash(bytes);
+
+        if !cfg!(feature = "random-seed") && saved_hash != hash {
+            return Err(invalid_data("istr snapshot entry has the wrong hash"));
+        }
+
+        entries.push((bytes, hash));
+    }
+
+    if !data.is_empty() {
+        return Err(invalid_data("trailing data in istr snapshot"));
+    }
+
+    for (bytes, hash) in entries {
+        cache::new_imp(bytes, hash);
+    }
+
+    Ok(count as usize)
+}
+
+#[test]
+fn test_round_trip() {
+    use crate::IBytes;
+
+    let a = IBytes::new(b"snapshot round trip");
+    let b = IBytes::new(b"snapshot \xff bytes");
+
+    let path = std::env::temp_dir().join(format!("istr-snapshot-{}", std::process::id()));
+    save(&path).unwrap();
+    let count = load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(count >= 2);
+    assert_eq!(IBytes::get_skip_local(b"snapshot round trip"), Some(a));
+    assert_eq!(IBytes::get_skip_local(b"snapshot \xff bytes"), Some(b));
+}
+
+#[test]
+fn test_invalid() {
+    assert!(read(&b"not a snapshot"[..]).is_err());
+
+    crate::IBytes::new(b"snapshot invalid");
+    let mut buffer = Vec::new();
+    write(&mut buffer).unwrap();
+
+    let mut wrong_fingerprint = buffer.clone();
+    wrong_fingerprint[16] ^= 1;
+    assert!(read(wrong_fingerprint.as_slice()).is_err());
+
+    assert!(read(&buffer[..buffer.len() - 1]).is_err());
+
+    if !cfg!(feature = "random-seed") {
+        // the first entry's saved hash
+        let mut wrong_hash = buffer.clone();
+        wrong_hash[32] ^= 1;
+        assert!(read(wrong_hash.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,79 @@
+use crate::{cache, hasher, IStr};
+
+// `static_istr!` creates an `IStr` without allocating, backed by a `static`. It can be used to
+// initialize `static` and `const` items, but since the hash can't be computed at compile time
+// every string must be passed to `register_static` before it is used. With debug assertions,
+// reading the hash of a string that wasn't registered panics.
+//
+// Empty strings are rejected at compile time, use `IStr::empty()` instead.
+#[macro_export]
+macro_rules! static_istr {
+    ($s:expr) => {{
+        static DATA: $crate::__private::StaticIStrData<{ $s.len() + 1 }> =
+            $crate::__private::StaticIStrData::new($s);
+        DATA.istr()
+    }};
+}
+
+/// Adds the strings to the global cache, so that `IStr::new` returns them from now on.
+///
+/// If a string with the same contents was already interned, that string is returned
+/// as an error, and the static string won't be returned by `IStr::new`.
+///
+/// # Safety
+///
+/// The strings must not be used in any other way before this function returns, and
+/// this function must not be called on the same strings from multiple threads at once.
+///
+/// Every string must be registered before it's hashed, compared with strings from the
+/// interner, or used as a map key. Until then its `saved_hash` is wrong, and it's never
+/// equal to the interned string with the same contents.
+pub unsafe fn register_static(strings: &[IStr]) -> Result<(), IStr> {
+    let mut result = Ok(());
+
+    for &istr in strings {
+        let ibytes = istr.to_ibytes();
+        let hash = hasher::hash(ibytes.to_bytes());
+        ibytes.init_hash(hash);
+
+        let interned = cache::TABLES.get_or_insert_with(ibytes.to_bytes(), hash, || ibytes);
+
+        if interned != ibytes && result.is_ok() {
+            result = Err(unsafe { IStr::from_utf8_unchecked(interned) });
+        }
+    }
+
+    result
+}
+
+#[test]
+fn test_register() {
+    static KW_FN: IStr = static_istr!("static fn");
+    const KW_LET: IStr = static_istr!("static let");
+
+    unsafe { register_static(&[KW_FN, KW_LET]).unwrap() }
+
+    assert_eq!(IStr::new("static fn"), KW_FN);
+    assert_eq!(IStr::new("static let"), KW_LET);
+    assert_eq!(KW_FN.saved_hash(), hasher::hash(b"static fn"));
+    assert_eq!(KW_FN.as_cstr().to_bytes(), b"static fn");
+
+    unsafe { register_static(&[KW_FN]).unwrap() }
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic = "before it was passed to register_static"]
+fn test_unregistered() {
+    static KW_UNREGISTERED: IStr = static_istr!("static unregistered");
+    KW_UNREGISTERED.saved_hash();
+}
+
+#[test]
+fn test_conflict() {
+    static KW_CONFLICT: IStr = static_istr!("static conflict");
+
+    let existing = IStr::new("static conflict");
+    assert_eq!(unsafe { register_static(&[KW_CONFLICT]) }, Err(existing));
+    assert_eq!(KW_CONFLICT.saved_hash(), existing.saved_hash());
+}
@@ -0,0 +1,205 @@
+use alloc::{boxed::Box, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::sync::Mutex;
+
+// Memory held by the arenas backing interned strings. This covers the global interner and every
+// live `Interner`, the reference counted `ArcIStr`s and the lookup tables aren't included.
+//
+// `reserved_bytes` includes the dedicated large allocations, and `used_bytes` includes the
+// strings stored in them. The bytes at the end of each chunk that are neither used, padding, nor
+// stranded are still free.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    // the number of arenas that have allocated at least one chunk, one per thread that
+    // interned a string, plus one per `Interner`
+    pub arenas: usize,
+    pub chunks: usize,
+    pub reserved_bytes: usize,
+    pub used_bytes: usize,
+    // the bytes skipped to keep each string aligned
+    pub padding_bytes: usize,
+    pub large_allocs: usize,
+    pub large_bytes: usize,
+    // the unused bytes at the end of chunks that will never be allocated from again, either
+    // because the arena moved on to a larger chunk or because the thread exited
+    pub stranded_bytes: usize,
+}
+
+impl MemoryStats {
+    fn add(&mut self, other: &MemoryStats) {
+        self.arenas += other.arenas;
+        self.chunks += other.chunks;
+        self.reserved_bytes += other.reserved_bytes;
+        self.used_bytes += other.used_bytes;
+        self.padding_bytes += other.padding_bytes;
+        self.large_allocs += other.large_allocs;
+        self.large_bytes += other.large_bytes;
+        self.stranded_bytes += other.stranded_bytes;
+    }
+}
+
+// Each arena counts its own stats, and copies them into its slot whenever it starts a new
+// chunk, makes a large allocation, or is retired, and when the stats are read from its thread.
+// Only the owning arena writes to a slot, and each slot is on its own cache line, so arenas
+// never contend on the counters.
+#[repr(align(128))]
+#[derive(Default)]
+pub(crate) struct StatsSlot {
+    arenas: AtomicUsize,
+    chunks: AtomicUsize,
+    reserved_bytes: AtomicUsize,
+    used_bytes: AtomicUsize,
+    padding_bytes: AtomicUsize,
+    large_allocs: AtomicUsize,
+    large_bytes: AtomicUsize,
+    stranded_bytes: AtomicUsize,
+}
+
+impl StatsSlot {
+    pub(crate) fn publish(&self, stats: &MemoryStats) {
+        self.arenas.store(stats.arenas, Ordering::Relaxed);
+        self.chunks.store(stats.chunks, Ordering::Relaxed);
+        self.reserved_bytes
+            .store(stats.reserved_bytes, Ordering::Relaxed);
+        self.used_bytes.store(stats.used_bytes, Ordering::Relaxed);
+        self.padding_bytes
+            .store(stats.padding_bytes, Ordering::Relaxed);
+        self.large_allocs
+            .store(stats.large_allocs, Ordering::Relaxed);
+        self.large_bytes.store(stats.large_bytes, Ordering::Relaxed);
+        self.stranded_bytes
+            .store(stats.stranded_bytes, Ordering::Relaxed);
+    }
+
+    fn load(&self) -> MemoryStats {
+        MemoryStats {
+            arenas: self.arenas.load(Ordering::Relaxed),
+            chunks: self.chunks.load(Ordering::Relaxed),
+            reserved_bytes: self.reserved_bytes.load(Ordering::Relaxed),
+            used_bytes: self.used_bytes.load(Ordering::Relaxed),
+            padding_bytes: self.padding_bytes.load(Ordering::Relaxed),
+            large_allocs: self.large_allocs.load(Ordering::Relaxed),
+            large_bytes: self.large_bytes.load(Ordering::Relaxed),
+            stranded_bytes: self.stranded_bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+struct Registration {
+    slot: &'static StatsSlot,
+    live: bool,
+    // the thread that owns the arena, if it's a per-thread arena
+    #[cfg(feature = "std")]
+    thread: Option<std::thread::ThreadId>,
+}
+
+// The slots of deallocated arenas are reused, so there is never more than one slot per live arena
+static REGISTRY: Mutex<Vec<Registration>> = Mutex::new(Vec::new());
+
+pub(crate) fn register(per_thread: bool) -> &'static StatsSlot {
+    #[cfg(feature = "std")]
+    let thread = per_thread.then(|| std::thread::current().id());
+    #[cfg(not(feature = "std"))]
+    let _ = per_thread;
+
+    let registry = &mut *REGISTRY.lock();
+
+    let registration = match registry.iter_mut().find(|registration| !registration.live) {
+        Some(registration) => registration,
+        None => {
+            registry.push(Registration {
+                slot: Box::leak(Box::default()),
+                live: false,
+                #[cfg(feature = "std")]
+                thread: None,
+            });
+            registry.last_mut().unwrap()
+        }
+    };
+
+    registration.live = true;
+    #[cfg(feature = "std")]
+    {
+        registration.thread = thread;
+    }
+
+    registration.slot
+}
+
+pub(crate) fn unregister(slot: &'static StatsSlot) {
+    slot.publish(&MemoryStats::default());
+
+    let registry = &mut *REGISTRY.lock();
+
+    if let Some(registration) = registry
+        .iter_mut()
+        .find(|registration| core::ptr::eq(registration.slot, slot))
+    {
+        registration.live = false;
+        #[cfg(feature = "std")]
+        {
+            registration.thread = None;
+        }
+    }
+}
+
+// The current thread's arena is published first, the other arenas' `used_bytes` and
+// `padding_bytes` may be behind by up to one chunk each.
+pub fn memory_stats() -> MemoryStats {
+    crate::leaky_alloc::publish_local();
+
+    let mut stats = MemoryStats::default();
+
+    for registration in REGISTRY.lock().iter() {
+        stats.add(&registration.slot.load());
+    }
+
+    stats
+}
+
+// The stats of every thread that interned a string, including threads that already exited, since
+// their strings are never deallocated. With `single-threaded` there are no per-thread arenas, so
+// this is always empty.
+#[cfg(feature = "std")]
+pub fn thread_memory_stats() -> Vec<(std::thread::ThreadId, MemoryStats)> {
+    crate::leaky_alloc::publish_local();
+
+    REGISTRY
+        .lock()
+        .iter()
+        .filter_map(|registration| Some((registration.thread?, registration.slot.load())))
+        .collect()
+}
+
+// the stats for the current thread's arena
+pub fn local_memory_stats() -> MemoryStats {
+    crate::leaky_alloc::local_stats()
+}
+
+#[test]
+fn test_stats() {
+    let before = local_memory_stats();
+    crate::IBytes::new_skip_local(b"memory stats");
+    crate::IBytes::new_skip_local(&[b'x'; 4 * 1024 * 1024]);
+    let after = local_memory_stats();
+
+    assert_eq!(after.arenas, 1);
+    assert!(after.chunks >= 1);
+    assert!(after.used_bytes > before.used_bytes + 4 * 1024 * 1024);
+    assert_eq!(after.large_allocs, before.large_allocs + 1);
+    assert!(after.reserved_bytes >= after.used_bytes + after.padding_bytes);
+
+    let global = memory_stats();
+    assert!(global.large_allocs >= 1);
+    assert!(global.reserved_bytes >= after.reserved_bytes);
+    assert!(global.used_bytes >= after.used_bytes);
+
+    #[cfg(all(feature = "std", not(feature = "single-threaded")))]
+    {
+        let current = std::thread::current().id();
+        let threads = thread_memory_stats();
+        let (_, local) = threads.iter().find(|(id, _)| *id == current).unwrap();
+        assert!(local.used_bytes >= after.used_bytes);
+    }
+}
@@ -0,0 +1,171 @@
+use alloc::{boxed::Box, vec};
+use core::{
+    num::NonZeroU32,
+    sync::atomic::{AtomicPtr, AtomicU32, Ordering},
+};
+
+use crate::{sync::Mutex, IStr};
+
+// Symbols are stored in an append-only vector made of segments that double in size, so
+// existing entries never move and can be read without taking `LOCK`.
+const FIRST_SEGMENT_BITS: u32 = 10;
+const SEGMENT_COUNT: usize = 32 - FIRST_SEGMENT_BITS as usize + 1;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const SEGMENT_INIT: AtomicPtr<IStr> = AtomicPtr::new(core::ptr::null_mut());
+
+static SEGMENTS: [AtomicPtr<IStr>; SEGMENT_COUNT] = [SEGMENT_INIT; SEGMENT_COUNT];
+static LEN: AtomicU32 = AtomicU32::new(0);
+static LOCK: Mutex<()> = Mutex::new(());
+
+// stores the index plus one, so that `Option<Symbol>` is the same size as `Symbol`
+#[repr(transparent)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Symbol(NonZeroU32);
+
+impl nohash_hasher::IsEnabled for Symbol {}
+
+fn segment_of(index: u32) -> (usize, usize) {
+    let offset = u64::from(index) + (1 << FIRST_SEGMENT_BITS);
+    let segment = 63 - offset.leading_zeros() - FIRST_SEGMENT_BITS;
+    let start = 1u64 << (segment + FIRST_SEGMENT_BITS);
+    (segment as usize, (offset - start) as usize)
+}
+
+fn segment_len(segment: usize) -> usize {
+    1 << (segment + FIRST_SEGMENT_BITS as usize)
+}
+
+#[cold]
+#[inline(never)]
+fn assign(istr: IStr) -> Symbol {
+    let _guard = LOCK.lock();
+    let slot = istr.to_ibytes().symbol_slot();
+
+    if let Some(symbol) = NonZeroU32::new(slot.load(Ordering::Acquire)) {
+        return Symbol(symbol);
+    }
+
+    let index = LEN.load(Ordering::Relaxed);
+    let symbol = index
+        .checked_add(1)
+        .and_then(NonZeroU32::new)
+        .expect("Overflow while assigning a symbol");
+
+    let (segment, offset) = segment_of(index);
+    let mut ptr = SEGMENTS[segment].load(Ordering::Relaxed);
+
+    if ptr.is_null() {
+        let storage = vec![IStr::empty(); segment_len(segment)].into_boxed_slice();
+        ptr = Box::leak(storage).as_mut_ptr();
+        SEGMENTS[segment].store(ptr, Ordering::Release);
+    }
+
+    unsafe { ptr.add(offset).write(istr) }
+
+    // publish the entry before the symbol can be observed through `LEN` or the string's header
+    LEN.store(symbol.get(), Ordering::Release);
+    slot.store(symbol.get(), Ordering::Release);
+
+    Symbol(symbol)
+}
+
+impl IStr {
+    #[inline]
+    pub fn symbol(self) -> Symbol {
+        match NonZeroU32::new(self.to_ibytes().symbol_slot().load(Ordering::Acquire)) {
+            Some(symbol) => Symbol(symbol),
+            None => assign(self),
+        }
+    }
+}
+
+impl Symbol {
+    // the number of symbols that have been assigned so far
+    pub fn count() -> usize {
+        LEN.load(Ordering::Acquire) as usize
+    }
+
+    #[inline]
+    pub fn from_index(index: usize) -> Option<Self> {
+        if index < Self::count() {
+            Some(Symbol(NonZeroU32::new(index as u32 + 1)?))
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    pub fn index(self) -> usize {
+        self.0.get() as usize - 1
+    }
+
+    #[inline]
+    pub fn as_u32(self) -> u32 {
+        self.0.get() - 1
+    }
+
+    #[inline]
+    pub fn resolve(self) -> IStr {
+        let (segment, offset) = segment_of(self.as_u32());
+        let ptr = SEGMENTS[segment].load(Ordering::Acquire);
+        unsafe { *ptr.add(offset) }
+    }
+}
+
+impl From<IStr> for Symbol {
+    #[inline]
+    fn from(value: IStr) -> Self {
+        value.symbol()
+    }
+}
+
+impl From<Symbol> for IStr {
+    #[inline]
+    fn from(value: Symbol) -> Self {
+        value.resolve()
+    }
+}
+
+impl core::fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Symbol({}, {:?})", self.as_u32(), self.resolve())
+    }
+}
+
+impl core::fmt::Display for Symbol {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.resolve().fmt(f)
+    }
+}
+
+#[test]
+fn test_simple() {
+    let a = IStr::new("symbol hello");
+    assert_eq!(a.symbol(), a.symbol());
+    assert_eq!(a.symbol().resolve(), a);
+    assert_ne!(IStr::new("symbol world").symbol(), a.symbol());
+    assert_eq!(Symbol::from_index(a.symbol().index()), Some(a.symbol()));
+    assert_eq!(core::mem::size_of::<Option<Symbol>>(), 4);
+}
+
+#[test]
+fn test_segments() {
+    assert_eq!(segment_of(0), (0, 0));
+    assert_eq!(segment_of(1023), (0, 1023));
+    assert_eq!(segment_of(1024), (1, 0));
+    assert_eq!(segment_of(3071), (1, 2047));
+    assert_eq!(segment_of(3072), (2, 0));
+    assert_eq!(segment_of(u32::MAX - 1).0, SEGMENT_COUNT - 1);
+}
+
+#[test]
+fn test_many() {
+    let symbols = (0..4096)
+        .map(|i| IStr::new(&format!("symbol {i}")).symbol())
+        .collect::<Vec<_>>();
+
+    for (i, symbol) in symbols.into_iter().enumerate() {
+        assert_eq!(symbol.resolve().to_str(), format!("symbol {i}"));
+    }
+}
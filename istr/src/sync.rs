@@ -0,0 +1,229 @@
+// The locks used by the interner. With `std` these wrap the `std::sync` types and ignore
+// poisoning, since none of the guarded state can be left inconsistent by a panic. Without `std`
+// they spin, which is fine since they are only held for short, bounded sections.
+
+#[cfg(feature = "std")]
+pub(crate) use std::sync::{MutexGuard, OnceLock};
+
+#[cfg(feature = "std")]
+pub(crate) struct Mutex<T>(std::sync::Mutex<T>);
+
+#[cfg(feature = "std")]
+impl<T> Mutex<T> {
+    pub(crate) const fn new(value: T) -> Self {
+        Self(std::sync::Mutex::new(value))
+    }
+
+    pub(crate) fn lock(&self) -> MutexGuard<'_, T> {
+        self.0
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut T {
+        self.0
+            .get_mut()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) use spin::{Mutex, MutexGuard, OnceLock};
+
+#[cfg(not(feature = "std"))]
+mod spin {
+    use core::{
+        cell::UnsafeCell,
+        mem::MaybeUninit,
+        ops::{Deref, DerefMut},
+        sync::atomic::{AtomicBool, AtomicU8, Ordering},
+    };
+
+    pub(crate) struct Mutex<T> {
+        locked: AtomicBool,
+        value: UnsafeCell<T>,
+    }
+
+    unsafe impl<T: Send> Send for Mutex<T> {}
+    unsafe impl<T: Send> Sync for Mutex<T> {}
+
+    pub(crate) struct MutexGuard<'a, T>(&'a Mutex<T>);
+
+    impl<T> Mutex<T> {
+        pub(crate) const fn new(value: T) -> Self {
+            Self {
+                locked: AtomicBool::new(false),
+                value: UnsafeCell::new(value),
+            }
+        }
+
+        pub(crate) fn lock(&self) -> MutexGuard<'_, T> {
+            while self
+                .locked
+                .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+            {
+                while self.locked.load(Ordering::Relaxed) {
+                    core::hint::spin_loop()
+                }
+            }
+
+            MutexGuard(self)
+        }
+
+        pub(crate) fn get_mut(&mut self) -> &mut T {
+            self.value.get_mut()
+        }
+    }
+
+    impl<T> Deref for MutexGuard<'_, T> {
+        type Target = T;
+
+        fn deref(&self) -> &T {
+            unsafe { &*self.0.value.get() }
+        }
+    }
+
+    impl<T> DerefMut for MutexGuard<'_, T> {
+        fn deref_mut(&mut self) -> &mut T {
+            unsafe { &mut *self.0.value.get() }
+        }
+    }
+
+    impl<T> Drop for MutexGuard<'_, T> {
+        fn drop(&mut self) {
+            self.0.locked.store(false, Ordering::Release)
+        }
+    }
+
+    const INCOMPLETE: u8 = 0;
+    const RUNNING: u8 = 1;
+    const COMPLETE: u8 = 2;
+
+    pub(crate) struct OnceLock<T> {
+        state: AtomicU8,
+        value: UnsafeCell<MaybeUninit<T>>,
+    }
+
+    unsafe impl<T: Send> Send for OnceLock<T> {}
+    unsafe impl<T: Send + Sync> Sync for OnceLock<T> {}
+
+    impl<T> OnceLock<T> {
+        pub(crate) const fn new() -> Self {
+            Self {
+                state: AtomicU8::new(INCOMPLETE),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            }
+        }
+
+        // returns `None` if another thread already started initializing the value
+        fn try_init(&self, f: impl FnOnce() -> T) -> Option<&T> {
+            self.state
+                .compare_exchange(INCOMPLETE, RUNNING, Ordering::Acquire, Ordering::Acquire)
+                .ok()?;
+
+            let value = unsafe { (*self.value.get()).write(f()) };
+            self.state.store(COMPLETE, Ordering::Release);
+            Some(value)
+        }
+
+        pub(crate) fn get_or_init(&self, f: impl FnOnce() -> T) -> &T {
+            if self.state.load(Ordering::Acquire) != COMPLETE {
+                if let Some(value) = self.try_init(f) {
+                    return value;
+                }
+
+                while self.state.load(Ordering::Acquire) != COMPLETE {
+                    core::hint::spin_loop()
+                }
+            }
+
+            unsafe { (*self.value.get()).assume_init_ref() }
+        }
+
+        pub(crate) fn set(&self, value: T) -> Result<(), T> {
+            let mut value = Some(value);
+
+            if self.try_init(|| value.take().unwrap()).is_some() {
+                Ok(())
+            } else {
+                Err(value.unwrap())
+            }
+        }
+    }
+
+    impl<T> Drop for OnceLock<T> {
+        fn drop(&mut self) {
+            if *self.state.get_mut() == COMPLETE {
+                unsafe { self.value.get_mut().assume_init_drop() }
+            }
+        }
+    }
+}
+
+// A lock that never waits, used with `single-threaded` where using the interner from two threads
+// at once is a bug in the program. It still has to be checked, since the strings can be sent to
+// other threads, but that costs a single uncontended atomic swap.
+//
+// Features are unified across the whole build, so a library enabling `single-threaded` turns
+// this on for every other user of the crate too, which is why it panics with a message naming
+// the feature instead of deadlocking or racing.
+#[cfg(feature = "single-threaded")]
+pub(crate) struct Exclusive<T> {
+    busy: core::sync::atomic::AtomicBool,
+    value: core::cell::UnsafeCell<T>,
+}
+
+#[cfg(feature = "single-threaded")]
+unsafe impl<T: Send> Sync for Exclusive<T> {}
+
+#[cfg(feature = "single-threaded")]
+impl<T> Exclusive<T> {
+    pub(crate) const fn new(value: T) -> Self {
+        Self {
+            busy: core::sync::atomic::AtomicBool::new(false),
+            value: core::cell::UnsafeCell::new(value),
+        }
+    }
+
+    pub(crate) fn with<O>(&self, f: impl FnOnce(&mut T) -> O) -> O {
+        use core::sync::atomic::{AtomicBool, Ordering};
+
+        struct Release<'a>(&'a AtomicBool);
+
+        impl Drop for Release<'_> {
+            fn drop(&mut self) {
+                self.0.store(false, Ordering::Release)
+            }
+        }
+
+        #[cold]
+        fn busy() -> ! {
+            panic!("the interner was used from more than one thread at once, which isn't supported with the `single-threaded` feature")
+        }
+
+        if self.busy.swap(true, Ordering::Acquire) {
+            busy()
+        }
+
+        let _release = Release(&self.busy);
+        f(unsafe { &mut *self.value.get() })
+    }
+}
+
+#[cfg(feature = "single-threaded")]
+#[test]
+fn test_exclusive() {
+    let cell = Exclusive::new(0);
+
+    std::thread::scope(|s| {
+        s.spawn(|| cell.with(|value| *value += 1));
+    });
+    cell.with(|value| *value += 1);
+
+    let reentrant = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        cell.with(|_| cell.with(|_| ()))
+    }));
+    assert!(reentrant.is_err());
+    assert_eq!(cell.with(|value| *value), 2);
+}
@@ -0,0 +1,633 @@
+use alloc::{alloc::Layout, vec::Vec};
+use core::{
+    ptr::NonNull,
+    sync::atomic::{AtomicPtr, AtomicUsize, Ordering},
+};
+
+#[cfg(feature = "single-threaded")]
+use hashbrown::raw::RawTable;
+
+#[cfg(feature = "single-threaded")]
+use crate::sync::Exclusive;
+use crate::{sync::Mutex, IBytes};
+
+// An insert-only open addressing hash table where lookups never block.
+//
+// Every slot starts out empty (null) and is filled exactly once with a compare-exchange, so
+// two threads inserting the same string race for the same first empty slot and the loser
+// sees the winner's string. Since entries are never removed, any string found in a slot stays
+// valid forever.
+//
+// Growing the table is serialized by `resize`. The resizer seals every empty slot of the old
+// array with `MOVED`, copies the entries over to a new array, and then publishes it. Inserts
+// that run into a sealed slot wait for the new array, but lookups can just stop at a sealed
+// slot, since any insert that didn't make it into the old array hasn't finished yet.
+//
+// Old arrays may still be read by other threads, so they are only freed once the table is
+// dropped. Each array is twice the size of the last one, so this at most doubles the memory
+// used by the table.
+pub(crate) struct ConcurrentTable {
+    slots: AtomicPtr<Slots>,
+    len: AtomicUsize,
+    resize: Mutex<Vec<*mut Slots>>,
+}
+
+unsafe impl Send for ConcurrentTable {}
+unsafe impl Sync for ConcurrentTable {}
+
+const MOVED: *mut u8 = usize::MAX as *mut u8;
+const INITIAL_CAPACITY: usize = 16;
+
+// the number of times the current thread grew a table, so tests can check that a call didn't
+// grow anything without racing with the other tests
+#[cfg(test)]
+std::thread_local! {
+    static GROWN: core::cell::Cell<usize> = const { core::cell::Cell::new(0) };
+}
+
+#[cfg(test)]
+pub(crate) fn grown() -> usize {
+    GROWN.with(|grown| grown.get())
+}
+
+struct Slot {
+    // the low bits of the hash, or zero if they haven't been written yet. This lets most
+    // mismatches be skipped without reading the string's header
+    tag: AtomicUsize,
+    ptr: AtomicPtr<u8>,
+}
+
+#[repr(C)]
+struct Slots {
+    mask: usize,
+    slots: [Slot; 0],
+}
+
+enum Probe {
+    Found(IBytes),
+    Vacant(usize),
+    Moved,
+    Full,
+}
+
+impl Slots {
+    fn layout(capacity: usize) -> Layout {
+        Layout::new::<Slots>()
+            .extend(Layout::array::<Slot>(capacity).unwrap())
+            .unwrap()
+            .0
+            .pad_to_align()
+    }
+
+    fn alloc(capacity: usize) -> *mut Slots {
+        debug_assert!(capacity.is_power_of_two());
+
+        let layout = Self::layout(capacity);
+        // all zeros is a valid empty slot
+        let ptr = unsafe { alloc::alloc::alloc_zeroed(layout) }.cast::<Slots>();
+
+        if ptr.is_null() {
+            alloc::alloc::handle_alloc_error(layout)
+        }
+
+        unsafe { core::ptr::addr_of_mut!((*ptr).mask).write(capacity - 1) }
+
+        ptr
+    }
+
+    unsafe fn dealloc(ptr: *mut Slots) {
+        alloc::alloc::dealloc(ptr.cast(), Self::layout(Self::capacity(ptr)))
+    }
+
+    unsafe fn capacity(ptr: *const Slots) -> usize {
+        (*ptr).mask + 1
+    }
+
+    unsafe fn slot<'a>(ptr: *const Slots, index: usize) -> &'a Slot {
+        let index = index & (*ptr).mask;
+        &*core::ptr::addr_of!((*ptr).slots).cast::<Slot>().add(index)
+    }
+
+    unsafe fn find(ptr: *const Slots, s: &[u8], hash: u64) -> Probe {
+        let mut index = hash as usize;
+
+        for _ in 0..Self::capacity(ptr) {
+            let slot = Self::slot(ptr, index);
+            let entry = slot.ptr.load(Ordering::Acquire);
+
+            if entry.is_null() {
+                return Probe::Vacant(index);
+            }
+
+            if entry == MOVED {
+                return Probe::Moved;
+            }
+
+            let tag = slot.tag.load(Ordering::Relaxed);
+
+            if tag == 0 || tag == hash as usize {
+                let ibytes = IBytes::from_ptr(NonNull::new_unchecked(entry));
+
+                if ibytes.saved_hash() == hash && ibytes.to_bytes() == s {
+                    return Probe::Found(ibytes);
+                }
+            }
+
+            index = index.wrapping_add(1);
+        }
+
+        Probe::Full
+    }
+
+    // only used while the table is private to the resizer
+    unsafe fn insert_unique(ptr: *const Slots, ibytes: IBytes) {
+        let hash = ibytes.saved_hash();
+        let mut index = hash as usize;
+
+        loop {
+            let slot = Self::slot(ptr, index);
+
+            if slot.ptr.load(Ordering::Relaxed).is_null() {
+                slot.tag.store(hash as usize, Ordering::Relaxed);
+                slot.ptr.store(ibytes.as_ptr().as_ptr(), Ordering::Relaxed);
+                return;
+            }
+
+            index = index.wrapping_add(1);
+        }
+    }
+
+    unsafe fn iter<'a>(ptr: *const Slots) -> impl Iterator<Item = IBytes> + 'a {
+        let capacity = if ptr.is_null() {
+            0
+        } else {
+            Self::capacity(ptr)
+        };
+
+        (0..capacity).filter_map(move |index| {
+            let entry = Self::slot(ptr, index).ptr.load(Ordering::Acquire);
+
+            if entry == MOVED {
+                None
+            } else {
+                NonNull::new(entry).map(|entry| IBytes::from_ptr(entry))
+            }
+        })
+    }
+}
+
+impl Drop for ConcurrentTable {
+    fn drop(&mut self) {
+        let slots = *self.slots.get_mut();
+        let retired = self.resize.get_mut();
+
+        for &ptr in retired.iter().chain(Some(&slots)) {
+            if !ptr.is_null() {
+                unsafe { Slots::dealloc(ptr) }
+            }
+        }
+    }
+}
+
+impl ConcurrentTable {
+    pub(crate) const fn new() -> Self {
+        Self {
+            slots: AtomicPtr::new(core::ptr::null_mut()),
+            len: AtomicUsize::new(0),
+            resize: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn get(&self, s: &[u8], hash: u64) -> Option<IBytes> {
+        let mut slots = self.slots.load(Ordering::Acquire);
+
+        while !slots.is_null() {
+            match unsafe { Slots::find(slots, s, hash) } {
+                Probe::Found(ibytes) => return Some(ibytes),
+                Probe::Vacant(_) | Probe::Full => return None,
+                Probe::Moved => {
+                    let current = self.slots.load(Ordering::Acquire);
+
+                    // the new array isn't published yet, so no insert into it has completed
+                    if current == slots {
+                        return None;
+                    }
+
+                    slots = current;
+                }
+            }
+        }
+
+        None
+    }
+
+    pub(crate) fn get_or_insert_with(
+        &self,
+        s: &[u8],
+        hash: u64,
+        create: impl FnOnce() -> IBytes,
+    ) -> IBytes {
+        let mut create = Some(create);
+        let mut created = None;
+        let mut slots = self.slots.load(Ordering::Acquire);
+
+        loop {
+            if slots.is_null() {
+                slots = self.grow(slots);
+                continue;
+            }
+
+            let index = match unsafe { Slots::find(slots, s, hash) } {
+                // if another thread inserted the same string first, the string created
+                // here is never used
+                Probe::Found(ibytes) => return ibytes,
+                Probe::Vacant(index) => index,
+                Probe::Moved | Probe::Full => {
+                    slots = self.grow(slots);
+                    continue;
+                }
+            };
+
+            let ibytes = *created.get_or_insert_with(|| insert_cold(create.take().unwrap()));
+            let slot = unsafe { Slots::slot(slots, index) };
+
+            let inserted = slot.ptr.compare_exchange(
+                core::ptr::null_mut(),
+                ibytes.as_ptr().as_ptr(),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            );
+
+            if inserted.is_ok() {
+                slot.tag.store(hash as usize, Ordering::Relaxed);
+
+                let len = self.len.fetch_add(1, Ordering::Relaxed) + 1;
+
+                // keep the load factor under 3/4
+                if len * 4 > unsafe { Slots::capacity(slots) } * 3 {
+                    self.grow(slots);
+                }
+
+                return ibytes;
+            }
+
+            // lost the race for this slot, search again since it may have been the same string
+        }
+    }
+
+    // makes room for `additional` more strings, so inserting them won't have to grow the table
+    #[cfg_attr(feature = "single-threaded", allow(dead_code))]
+    pub(crate) fn reserve(&self, additional: usize) {
+        let required = (self.len() + additional)
+            .checked_mul(4)
+            .and_then(|len| len.div_ceil(3).checked_next_power_of_two())
+            .expect("capacity overflow")
+            .max(INITIAL_CAPACITY);
+
+        let mut slots = self.slots.load(Ordering::Acquire);
+
+        while slots.is_null() || unsafe { Slots::capacity(slots) } < required {
+            slots = self.grow_to(slots, required);
+        }
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn grow(&self, observed: *mut Slots) -> *mut Slots {
+        let capacity = if observed.is_null() {
+            INITIAL_CAPACITY
+        } else {
+            unsafe { Slots::capacity(observed) * 2 }
+        };
+
+        self.grow_to(observed, capacity)
+    }
+
+    fn grow_to(&self, observed: *mut Slots, new_capacity: usize) -> *mut Slots {
+        let mut retired = self.resize.lock();
+        let current = self.slots.load(Ordering::Acquire);
+
+        if current != observed {
+            // some other thread already grew the table
+            return current;
+        }
+
+        let slots = Slots::alloc(new_capacity);
+
+        #[cfg(test)]
+        GROWN.with(|grown| grown.set(grown.get() + 1));
+
+        if !observed.is_null() {
+            let capacity = unsafe { Slots::capacity(observed) };
+
+            for index in 0..capacity {
+                let slot = unsafe { Slots::slot(observed, index) };
+
+                let entry = loop {
+                    let entry = slot.ptr.load(Ordering::Acquire);
+
+                    if !entry.is_null() {
+                        break entry;
+                    }
+
+                    if slot
+                        .ptr
+                        .compare_exchange(entry, MOVED, Ordering::AcqRel, Ordering::Acquire)
+                        .is_ok()
+                    {
+                        break MOVED;
+                    }
+                };
+
+                if entry != MOVED {
+                    unsafe {
+                        Slots::insert_unique(slots, IBytes::from_ptr(NonNull::new_unchecked(entry)))
+                    }
+                }
+            }
+
+            retired.push(observed);
+        }
+
+        self.slots.store(slots, Ordering::Release);
+
+        slots
+    }
+
+    // The iterator sees every string that was inserted before it was created, and may or may
+    // not see strings that are inserted while it's running.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = IBytes> + '_ {
+        unsafe { Slots::iter(self.slots.load(Ordering::Acquire)) }
+    }
+
+    #[cfg(ISTR_GLOBAL_CACHE_CLEAR)]
+    #[cfg_attr(feature = "single-threaded", allow(dead_code))]
+    pub(crate) fn clear(&self) {
+        let mut retired = self.resize.lock();
+        let slots = self.slots.swap(core::ptr::null_mut(), Ordering::AcqRel);
+
+        if !slots.is_null() {
+            retired.push(slots);
+        }
+
+        self.len.store(0, Ordering::Relaxed);
+    }
+}
+
+#[cold]
+#[inline(never)]
+fn insert_cold(create: impl FnOnce() -> IBytes) -> IBytes {
+    create()
+}
+
+// keeps each shard on its own cache lines, so threads working on different shards don't contend
+#[repr(align(128))]
+pub(crate) struct CacheAligned<T>(pub(crate) T);
+
+#[allow(clippy::declare_interior_mutable_const)]
+const CACHE_ALIGN_TABLE_INIT: CacheAligned<ConcurrentTable> = CacheAligned(ConcurrentTable::new());
+
+// Constant for h2 function that grabing the top 7 bits of the hash.
+const MIN_HASH_LEN: usize = if core::mem::size_of::<usize>() < core::mem::size_of::<u64>() {
+    core::mem::size_of::<usize>()
+} else {
+    core::mem::size_of::<u64>()
+};
+
+pub(crate) fn shard_index(hash: u64, shards: usize) -> usize {
+    ((hash >> (MIN_HASH_LEN * 4)) % shards as u64) as usize
+}
+
+pub(crate) struct ShardedTable {
+    // choose a decently large prime number to prevent cache collisions
+    shards: [CacheAligned<ConcurrentTable>; 64],
+}
+
+impl ShardedTable {
+    pub(crate) const fn new() -> Self {
+        Self {
+            shards: [CACHE_ALIGN_TABLE_INIT; 64],
+        }
+    }
+
+    fn shard(&self, hash: u64) -> &ConcurrentTable {
+        &self.shards[shard_index(hash, self.shards.len())].0
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.0.len()).sum()
+    }
+
+    #[inline]
+    pub(crate) fn get(&self, s: &[u8], hash: u64) -> Option<IBytes> {
+        self.shard(hash).get(s, hash)
+    }
+
+    #[inline]
+    pub(crate) fn get_or_insert_with(
+        &self,
+        s: &[u8],
+        hash: u64,
+        create: impl FnOnce() -> IBytes,
+    ) -> IBytes {
+        self.shard(hash).get_or_insert_with(s, hash, create)
+    }
+
+    // reserves room in each shard for the strings with the given hashes
+    #[cfg_attr(feature = "single-threaded", allow(dead_code))]
+    pub(crate) fn reserve_hashes(&self, hashes: &[u64]) {
+        let mut counts = [0; 64];
+
+        for &hash in hashes {
+            counts[shard_index(hash, self.shards.len())] += 1;
+        }
+
+        for (shard, count) in self.shards.iter().zip(counts) {
+            if count != 0 {
+                shard.0.reserve(count);
+            }
+        }
+    }
+
+    #[cfg_attr(feature = "single-threaded", allow(dead_code))]
+    pub(crate) fn iter(&self) -> impl Iterator<Item = IBytes> + '_ {
+        self.shards.iter().flat_map(|shard| shard.0.iter())
+    }
+
+    #[cfg(ISTR_GLOBAL_CACHE_CLEAR)]
+    #[cfg_attr(feature = "single-threaded", allow(dead_code))]
+    pub(crate) fn clear(&self) {
+        self.shards.iter().for_each(|shard| shard.0.clear())
+    }
+}
+
+// The global table with `single-threaded`, a plain hash table with no shards or atomics
+#[cfg(feature = "single-threaded")]
+pub(crate) struct UnsyncTable {
+    table: Exclusive<RawTable<IBytes>>,
+}
+
+#[cfg(feature = "single-threaded")]
+impl UnsyncTable {
+    pub(crate) const fn new() -> Self {
+        Self {
+            table: Exclusive::new(RawTable::new()),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.table.with(|table| table.len())
+    }
+
+    #[inline]
+    pub(crate) fn get(&self, s: &[u8], hash: u64) -> Option<IBytes> {
+        self.table
+            .with(|table| table.get(hash, |ibytes| ibytes.to_bytes() == s).copied())
+    }
+
+    #[inline]
+    pub(crate) fn get_or_insert_with(
+        &self,
+        s: &[u8],
+        hash: u64,
+        create: impl FnOnce() -> IBytes,
+    ) -> IBytes {
+        self.table.with(|table| {
+            match table.find_or_find_insert_slot(
+                hash,
+                |ibytes| ibytes.to_bytes() == s,
+                |ibytes| ibytes.saved_hash(),
+            ) {
+                Ok(bucket) => unsafe { *bucket.as_ref() },
+                Err(slot) => {
+                    let ibytes = insert_cold(create);
+                    unsafe { table.insert_in_slot(hash, slot, ibytes) };
+                    ibytes
+                }
+            }
+        })
+    }
+
+    pub(crate) fn reserve_hashes(&self, hashes: &[u64]) {
+        self.table.with(|table| {
+            #[cfg(test)]
+            let buckets = table.buckets();
+
+            table.reserve(hashes.len(), |ibytes| ibytes.saved_hash());
+
+            #[cfg(test)]
+            if table.buckets() != buckets {
+                GROWN.with(|grown| grown.set(grown.get() + 1));
+            }
+        })
+    }
+
+    // collected up front, so that strings can be interned while iterating
+    pub(crate) fn iter(&self) -> impl Iterator<Item = IBytes> {
+        self.table
+            .with(|table| {
+                let buckets = unsafe { table.iter() };
+                buckets
+                    .map(|bucket| unsafe { *bucket.as_ref() })
+                    .collect::<Vec<_>>()
+            })
+            .into_iter()
+    }
+
+    #[cfg(ISTR_GLOBAL_CACHE_CLEAR)]
+    pub(crate) fn clear(&self) {
+        self.table.with(|table| table.clear())
+    }
+}
+
+#[test]
+fn test_grow() {
+    let table = ConcurrentTable::new();
+    let strings = (0..1024).map(|i| i.to_string()).collect::<Vec<_>>();
+
+    let interned = strings
+        .iter()
+        .map(|s| {
+            let hash = crate::hasher::hash(s.as_bytes());
+            table.get_or_insert_with(s.as_bytes(), hash, || {
+                crate::leaky_alloc::with_hash_bytes(s.as_bytes(), hash, true)
+            })
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(table.len(), 1024);
+    assert_eq!(table.iter().count(), 1024);
+
+    for (s, ibytes) in strings.iter().zip(interned) {
+        assert_eq!(
+            table.get(s.as_bytes(), crate::hasher::hash(s.as_bytes())),
+            Some(ibytes)
+        );
+    }
+}
+
+#[cfg(not(feature = "single-threaded"))]
+#[test]
+fn test_concurrent_inserts() {
+    let table = ConcurrentTable::new();
+
+    let results = std::thread::scope(|scope| {
+        let handles = (0..8)
+            .map(|_| {
+                scope.spawn(|| {
+                    (0..2048)
+                        .map(|i| {
+                            let s = format!("concurrent {i}");
+                            let hash = crate::hasher::hash(s.as_bytes());
+                            table.get_or_insert_with(s.as_bytes(), hash, || {
+                                crate::leaky_alloc::with_hash_bytes(s.as_bytes(), hash, true)
+                            })
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+
+    assert_eq!(table.len(), 2048);
+
+    for result in &results[1..] {
+        assert_eq!(result, &results[0]);
+    }
+}
+
+#[test]
+fn test_lookups_dont_lock() {
+    static TABLE: ConcurrentTable = ConcurrentTable::new();
+
+    let hash = crate::hasher::hash(b"lock free");
+    let ibytes = TABLE.get_or_insert_with(b"lock free", hash, || {
+        crate::leaky_alloc::with_hash_bytes(b"lock free", hash, true)
+    });
+
+    // hits and misses must both complete while a writer holds the resize lock, they run on
+    // another thread so that a regression fails the test instead of hanging it
+    let _guard = TABLE.resize.lock();
+    let (sender, receiver) = std::sync::mpsc::channel();
+
+    std::thread::spawn(move || {
+        let hit = TABLE.get(b"lock free", hash);
+        let miss = TABLE.get(b"lock free?", hash);
+        let insert_hit = TABLE.get_or_insert_with(b"lock free", hash, || unreachable!());
+        sender.send((hit, miss, insert_hit)).unwrap();
+    });
+
+    let result = receiver
+        .recv_timeout(std::time::Duration::from_secs(10))
+        .expect("lookups blocked on the resize lock");
+    assert_eq!(result, (Some(ibytes), None, ibytes));
+}